@@ -0,0 +1,123 @@
+use core::fmt::{Display, Write};
+use simple_interp::ArrayString;
+
+use crate::{
+    storage::fsck::Report,
+    surface::Surface,
+    vga::{Color, ColorCode},
+    NUM_BLOCKS,
+};
+
+use super::window::Window;
+
+// What the window wants run next. The file system is only
+// reachable from SwimInterface, so it asks for it there.
+#[derive(Clone, Copy)]
+pub enum Request {
+    Check,
+    Repair,
+}
+
+// k in an Explorer, shows what storage::fsck finds and f fixes what it can
+pub struct Check {
+    pub window: Window,
+    report: Option<Report>,
+    request: Option<Request>,
+    // the report is from after a repair
+    repaired: bool,
+    error: Option<ArrayString<64>>,
+}
+
+impl Check {
+    // Asks for a check straight away, so there's something to show
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            report: None,
+            request: Some(Request::Check),
+            repaired: false,
+            error: None,
+        }
+    }
+
+    pub fn title(&self) -> ArrayString<64> {
+        let mut a = ArrayString::<64>::default();
+        let _ = write!(a, "FSCK:(f)ix,chec(k),F6 exit");
+        a
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        match c {
+            'f' => self.request = Some(Request::Repair),
+            'k' => self.request = Some(Request::Check),
+            _ => {}
+        }
+    }
+
+    pub fn take_request(&mut self) -> Option<Request> {
+        self.request.take()
+    }
+
+    pub fn show(&mut self, report: Report, repaired: bool) {
+        self.report = Some(report);
+        self.repaired = repaired;
+        self.error = None;
+    }
+
+    // Keeps whatever report there was, so the error shows above it
+    pub fn error(&mut self, e: impl Display) {
+        let mut error = ArrayString::<64>::default();
+        let _ = write!(error, "ERROR {e}");
+        self.error = Some(error);
+    }
+
+    pub fn draw(&self, surface: &mut impl Surface) {
+        let mut row = 0;
+        let mut line = |text: &dyn Display| {
+            let mut a = ArrayString::<64>::default();
+            let _ = write!(a, "{text}");
+            if row < self.window.height() {
+                self.draw_line(surface, row, a.as_str().unwrap_or(""));
+                row += 1;
+            }
+        };
+
+        if let Some(error) = &self.error {
+            line(&error.as_str().unwrap_or("ERROR"));
+        }
+        if let Some(report) = &self.report {
+            if self.repaired {
+                line(&"After fixing:");
+            }
+            line(&format_args!(
+                "{} files, {} dirs, {}/{NUM_BLOCKS} blocks",
+                report.files, report.dirs, report.blocks
+            ));
+            if !report.disk {
+                line(&"No disk, files are only in RAM");
+            }
+            if report.is_clean() {
+                line(&"No problems found");
+            }
+            for problem in report.problems() {
+                line(problem);
+            }
+            if report.more > 0 {
+                line(&format_args!("...and {} more, f again", report.more));
+            }
+        }
+        for row in row..self.window.height() {
+            self.draw_line(surface, row, "");
+        }
+    }
+
+    // text on one row of the window, with the rest of the row blanked
+    fn draw_line(&self, surface: &mut impl Surface, row: usize, text: &str) {
+        let color = ColorCode::new(Color::LightGray, Color::Black);
+        let mut chars = text.chars();
+        for x in 0..self.window.width() {
+            let c = chars.next().unwrap_or(' ');
+            self.window.plot(surface, c, x as u8, row as u8, color);
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::MAX_FILE_BYTES;
+
+// There is one clipboard for the whole interface, owned by SwimInterface
+// and lent to whichever app is cutting, copying or pasting. It can hold
+// anything up to a whole file.
+pub struct Clipboard {
+    buf: [u8; MAX_FILE_BYTES],
+    len: usize,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            buf: [0; MAX_FILE_BYTES],
+            len: 0,
+        }
+    }
+}
+
+impl Clipboard {
+    pub fn set(&mut self, bytes: impl Iterator<Item = u8>) {
+        self.len = 0;
+        for b in bytes.take(MAX_FILE_BYTES) {
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
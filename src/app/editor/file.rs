@@ -0,0 +1,313 @@
+use core::fmt::{self, Display, Write};
+use simple_interp::ArrayString;
+
+use super::{History, Mode, TextEditor};
+use crate::{
+    app::{prompt::Prompt, window::Window},
+    fs::{self, FileSystemError},
+    scratch::with_file_buffer,
+    FsType, BLOCK_SIZE, MAX_FILENAME_BYTES, MAX_PATH_BYTES,
+};
+
+// Every save is written here and checked, and only then renamed over the
+// real file, so a save that goes wrong partway, even by the power going
+// out, leaves the old file as it was. It's gone again before save returns,
+// or at the next boot if save never got that far. Names starting with .
+// can't be typed anywhere, so nobody else can take it.
+pub(crate) const SAVE_FILE: &str = ".save";
+
+// The Save-As and Open prompts. Anything wrong with the name is
+// shown in place of what the prompt is for until the next key.
+#[derive(Default)]
+pub struct NamePrompt {
+    name: Prompt<MAX_PATH_BYTES>,
+    error: Option<ArrayString<32>>,
+}
+
+impl NamePrompt {
+    pub fn title(&self, what: &str) -> ArrayString<64> {
+        let mut a = ArrayString::<64>::default();
+        let what = match &self.error {
+            Some(error) => error.as_str().unwrap_or(what),
+            None => what,
+        };
+        let _ = write!(a, "{what}:{}", self.name.as_str());
+        a
+    }
+
+    fn validate(&self) -> Result<ArrayString<MAX_PATH_BYTES>, &'static str> {
+        if self.name.is_empty() {
+            return Err("no name");
+        }
+        if fs::is_hidden(self.name.as_str()) {
+            return Err("hidden name");
+        }
+        if self.name.as_str().split('/').any(|name| name.len() > MAX_FILENAME_BYTES) {
+            return Err("name too long");
+        }
+        let mut a = ArrayString::<MAX_PATH_BYTES>::default();
+        for c in self.name.as_str().chars() {
+            a.push_char(c);
+        }
+        Ok(a)
+    }
+}
+
+fn read_file(
+    fs: &mut FsType,
+    filename: &str,
+    buffer: &mut [u8],
+) -> Result<usize, FileSystemError> {
+    let fd = fs.open_read(filename)?;
+    // close it even if the read fails, or it's open for good
+    let n = fs.read(fd, buffer);
+    fs.close(fd)?;
+    n
+}
+
+enum SaveError {
+    Fs(FileSystemError),
+    // it was written, but what came back wasn't what went in
+    Verify,
+}
+
+impl From<FileSystemError> for SaveError {
+    fn from(e: FileSystemError) -> Self {
+        SaveError::Fs(e)
+    }
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Fs(e) => write!(f, "{e}"),
+            SaveError::Verify => write!(f, "didn't read back right"),
+        }
+    }
+}
+
+// Writes data over filename, then reads it back to make sure it all
+// got there. A block at a time, so it doesn't need a second file's worth
+// of stack on top of the one being saved.
+fn write_verified(fs: &mut FsType, filename: &str, data: &[u8]) -> Result<(), SaveError> {
+    let fd = fs.open_create(filename)?;
+    let written = fs.write(fd, data);
+    fs.close(fd)?;
+    written?;
+
+    let fd = fs.open_read(filename)?;
+    let mut chunk = [0u8; BLOCK_SIZE];
+    let mut at = 0;
+    let same = loop {
+        let n = match fs.read(fd, &mut chunk) {
+            Ok(n) => n,
+            Err(e) => {
+                fs.close(fd)?;
+                return Err(e.into());
+            }
+        };
+        if data.get(at..at + n) != Some(&chunk[..n]) {
+            break false;
+        }
+        at += n;
+        if n < chunk.len() {
+            break at == data.len();
+        }
+    };
+    fs.close(fd)?;
+    if same {
+        Ok(())
+    } else {
+        Err(SaveError::Verify)
+    }
+}
+
+// When there's no inode or no room for SAVE_FILE as well, the save goes
+// straight over the old file. Only if it's sure to fit in the old file's
+// blocks and what's free, though, since a write that doesn't fit leaves
+// the file empty.
+fn write_in_place(fs: &mut FsType, filename: &str, data: &[u8]) -> Result<(), SaveError> {
+    let old = match fs.file_size(filename) {
+        Ok(bytes) => bytes,
+        Err(FileSystemError::FileNotFound) => 0,
+        Err(e) => return Err(e.into()),
+    };
+    if data.len().div_ceil(BLOCK_SIZE) > old.div_ceil(BLOCK_SIZE) + fs.free_blocks()? {
+        return Err(FileSystemError::DiskFull.into());
+    }
+    write_verified(fs, filename, data)
+}
+
+impl TextEditor {
+    // A new editor with a file already loaded, for
+    // opening one from somewhere other than an Explorer
+    pub fn from_file(
+        window: Window,
+        filename: ArrayString<MAX_PATH_BYTES>,
+        fs: &mut FsType,
+    ) -> Result<Self, FileSystemError> {
+        let mut text = Self::new(window, filename);
+        with_file_buffer(|buffer| {
+            let n = read_file(fs, filename.as_str().unwrap_or(""), buffer)?;
+            text.load(&buffer[..n]);
+            Ok::<_, FileSystemError>(())
+        })?;
+        Ok(text)
+    }
+
+    pub(super) fn is_modified(&self) -> bool {
+        self.doc.version() != self.saved
+    }
+
+    // Writes the document back to its file, by way of SAVE_FILE. If that
+    // doesn't work the error stays in the title until the next save or Esc.
+    pub fn save(&mut self, fs: &mut FsType) -> bool {
+        let mut error = ArrayString::<64>::default();
+        match self.filename.as_str() {
+            Ok(filename) => {
+                let saved = with_file_buffer(|buffer| {
+                    let len = self.dump(buffer);
+                    let data = &buffer[..len];
+                    match write_verified(fs, SAVE_FILE, data)
+                        .and_then(|()| Ok(fs.rename(SAVE_FILE, filename)?))
+                    {
+                        Err(SaveError::Fs(
+                            FileSystemError::TooManyFiles | FileSystemError::DiskFull,
+                        )) => {
+                            let _ = fs.delete(SAVE_FILE);
+                            write_in_place(fs, filename, data)
+                        }
+                        saved => saved,
+                    }
+                });
+                if let Err(e) = saved {
+                    // the old file is untouched, and half a save isn't worth keeping
+                    let _ = fs.delete(SAVE_FILE);
+                    let _ = write!(error, "{e}");
+                } else {
+                    self.saved = self.doc.version();
+                    self.error = None;
+                    self.files_changed = true;
+                    let mut status = ArrayString::<64>::default();
+                    let _ = write!(status, "Saved {filename}");
+                    self.status = Some(status);
+                    return true;
+                }
+            }
+            Err(_) => {
+                let _ = write!(error, "bad file name");
+            }
+        }
+        self.error = Some(error);
+        false
+    }
+
+    // F6 closes the editor straight away if there is nothing to lose,
+    // otherwise it asks what to do with the changes first
+    pub fn exit(&mut self) -> bool {
+        if self.is_modified() {
+            self.mode = Mode::ConfirmExit;
+            false
+        } else {
+            true
+        }
+    }
+
+    // The answer to the F6 prompt, true if the editor should close
+    pub(super) fn confirm_exit(&mut self, c: char, fs: &mut FsType) -> bool {
+        match c {
+            's' => {
+                self.mode = Mode::Edit;
+                self.save(fs)
+            }
+            'd' => true,
+            'c' => {
+                self.mode = Mode::Edit;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    pub fn save_as(&mut self) {
+        self.mode = Mode::SaveAs(NamePrompt::default());
+    }
+
+    pub fn open(&mut self) {
+        self.mode = Mode::Open(NamePrompt::default());
+    }
+
+    pub(super) fn name_char(&mut self, c: char) {
+        if let Mode::SaveAs(prompt) | Mode::Open(prompt) = &mut self.mode {
+            prompt.name.push(c);
+            prompt.error = None;
+        }
+    }
+
+    pub(super) fn name_backspace(&mut self) {
+        if let Mode::SaveAs(prompt) | Mode::Open(prompt) = &mut self.mode {
+            prompt.name.pop();
+            prompt.error = None;
+        }
+    }
+
+    pub(super) fn name_newline(&mut self, fs: &mut FsType) {
+        let (name, opening) = match &self.mode {
+            Mode::SaveAs(prompt) => (prompt.validate(), false),
+            Mode::Open(prompt) => (prompt.validate(), true),
+            _ => return,
+        };
+        match name {
+            Ok(name) if opening => self.open_file(name, fs),
+            Ok(name) => self.save_as_file(name, fs),
+            Err(e) => self.name_error(e),
+        }
+    }
+
+    fn name_error(&mut self, e: impl Display) {
+        if let Mode::SaveAs(prompt) | Mode::Open(prompt) = &mut self.mode {
+            let mut error = ArrayString::<32>::default();
+            let _ = write!(error, "{e}");
+            prompt.error = Some(error);
+        }
+    }
+
+    // From here on the editor belongs to the new name, unless it can't be
+    // saved there. A name that's taken is refused, like the Explorer does.
+    fn save_as_file(&mut self, name: ArrayString<MAX_PATH_BYTES>, fs: &mut FsType) {
+        if fs.exists(name.as_str().unwrap_or("")) {
+            return self.name_error("already exists");
+        }
+        let old = core::mem::replace(&mut self.filename, name);
+        self.mode = Mode::Edit;
+        if !self.save(fs) {
+            self.filename = old;
+        }
+    }
+
+    fn open_file(&mut self, name: ArrayString<MAX_PATH_BYTES>, fs: &mut FsType) {
+        if self.is_modified() {
+            return self.name_error("unsaved changes");
+        }
+        let Ok(filename) = name.as_str() else {
+            return self.name_error("bad file name");
+        };
+
+        let read = with_file_buffer(|buffer| {
+            let n = read_file(fs, filename, buffer)?;
+            self.doc.clear();
+            self.cursor = 0;
+            self.anchor = None;
+            self.scroll = 0;
+            self.history = History::default();
+            self.error = None;
+            self.filename = name;
+            self.mode = Mode::Edit;
+            self.load(&buffer[..n]);
+            Ok::<_, FileSystemError>(())
+        });
+        if let Err(e) = read {
+            self.name_error(e);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use core::cell::{Ref, RefCell};
+
+use crate::MAX_FILE_BYTES;
+
+// The document is one big array of bytes with a hole (the gap) where the
+// cursor is. Typing fills the hole from the left and backspace widens it,
+// so edits near the cursor never shift the rest of the document. Moving
+// the gap somewhere else only copies the bytes in between.
+pub struct GapBuffer {
+    buf: [u8; MAX_FILE_BYTES],
+    gap_start: usize,
+    gap_end: usize,
+    // bumped on every change, so anything worked out from the
+    // contents can tell when it needs working out again
+    version: usize,
+    lines: RefCell<LineStarts>,
+}
+
+// Where each line starts, found in one pass and kept until the document
+// changes, like Brackets does for the unbalanced brackets. Drawing asks
+// about lines for every row, so this turns a scan from the top into a
+// lookup. Lines past the last one there's room for are scanned for from it.
+const MAX_LINE_STARTS: usize = 2048;
+const _: () = assert!(MAX_FILE_BYTES <= u16::MAX as usize);
+
+struct LineStarts {
+    version: Option<usize>,
+    starts: [u16; MAX_LINE_STARTS],
+    kept: usize,
+    count: usize,
+}
+
+impl Default for GapBuffer {
+    fn default() -> Self {
+        Self {
+            buf: [0; MAX_FILE_BYTES],
+            gap_start: 0,
+            gap_end: MAX_FILE_BYTES,
+            version: 0,
+            lines: RefCell::new(LineStarts {
+                version: None,
+                starts: [0; MAX_LINE_STARTS],
+                kept: 0,
+                count: 0,
+            }),
+        }
+    }
+}
+
+impl GapBuffer {
+    pub fn len(&self) -> usize {
+        MAX_FILE_BYTES - (self.gap_end - self.gap_start)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.gap_start == self.gap_end
+    }
+
+    // how many more bytes fit before the document is as big as a file can be
+    pub fn free(&self) -> usize {
+        self.gap_end - self.gap_start
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn get(&self, i: usize) -> Option<u8> {
+        if i < self.gap_start {
+            Some(self.buf[i])
+        } else if i < self.len() {
+            Some(self.buf[i + self.gap_end - self.gap_start])
+        } else {
+            None
+        }
+    }
+
+    pub fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.buf[..self.gap_start]
+            .iter()
+            .chain(self.buf[self.gap_end..].iter())
+            .copied()
+    }
+
+    fn move_gap(&mut self, pos: usize) {
+        if pos < self.gap_start {
+            let n = self.gap_start - pos;
+            self.buf.copy_within(pos..self.gap_start, self.gap_end - n);
+            self.gap_start -= n;
+            self.gap_end -= n;
+        } else if pos > self.gap_start {
+            let n = pos - self.gap_start;
+            self.buf
+                .copy_within(self.gap_end..self.gap_end + n, self.gap_start);
+            self.gap_start += n;
+            self.gap_end += n;
+        }
+    }
+
+    // false if the document is already as big as a file can be
+    pub fn insert(&mut self, pos: usize, b: u8) -> bool {
+        if self.is_full() || pos > self.len() {
+            return false;
+        }
+        self.move_gap(pos);
+        self.buf[self.gap_start] = b;
+        self.gap_start += 1;
+        self.version = self.version.wrapping_add(1);
+        true
+    }
+
+    pub fn remove(&mut self, pos: usize) -> Option<u8> {
+        if pos >= self.len() {
+            return None;
+        }
+        self.move_gap(pos);
+        let b = self.buf[self.gap_end];
+        self.gap_end += 1;
+        self.version = self.version.wrapping_add(1);
+        Some(b)
+    }
+
+    pub fn clear(&mut self) {
+        self.gap_start = 0;
+        self.gap_end = MAX_FILE_BYTES;
+        self.version = self.version.wrapping_add(1);
+    }
+
+    pub fn copy_to(&self, out: &mut [u8]) -> usize {
+        let mut n = 0;
+        for (dst, src) in out.iter_mut().zip(self.bytes()) {
+            *dst = src;
+            n += 1;
+        }
+        n
+    }
+
+    fn lines(&self) -> Ref<'_, LineStarts> {
+        if self.lines.borrow().version != Some(self.version) {
+            let mut lines = self.lines.borrow_mut();
+            lines.version = Some(self.version);
+            lines.kept = 1;
+            lines.count = 1;
+            for (i, b) in self.bytes().enumerate() {
+                if b == b'\n' {
+                    if lines.kept < MAX_LINE_STARTS {
+                        let kept = lines.kept;
+                        lines.starts[kept] = (i + 1) as u16;
+                        lines.kept += 1;
+                    }
+                    lines.count += 1;
+                }
+            }
+        }
+        self.lines.borrow()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines().count
+    }
+
+    // Offset of the first byte of line, or None past the last line
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        let lines = self.lines();
+        if line >= lines.count {
+            return None;
+        }
+        if line < lines.kept {
+            return Some(lines.starts[line] as usize);
+        }
+        let mut seen = lines.kept - 1;
+        let from = lines.starts[seen] as usize;
+        for (i, b) in self.bytes().enumerate().skip(from) {
+            if b == b'\n' {
+                seen += 1;
+                if seen == line {
+                    return Some(i + 1);
+                }
+            }
+        }
+        None
+    }
+
+    // Offset of the '\n' ending the line that contains pos,
+    // or the end of the document on the last line.
+    pub fn line_end(&self, pos: usize) -> usize {
+        (pos..self.len())
+            .find(|i| self.get(*i) == Some(b'\n'))
+            .unwrap_or(self.len())
+    }
+
+    // (line, col) of the byte at pos
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        let lines = self.lines();
+        let kept = &lines.starts[..lines.kept];
+        let mut line = kept.partition_point(|start| *start as usize <= pos) - 1;
+        let mut start = kept[line] as usize;
+        if line + 1 == lines.kept {
+            for (i, b) in self.bytes().enumerate().take(pos).skip(start) {
+                if b == b'\n' {
+                    line += 1;
+                    start = i + 1;
+                }
+            }
+        }
+        (line, pos - start)
+    }
+
+    pub fn matches_at(&self, pos: usize, pattern: &[u8]) -> bool {
+        pos + pattern.len() <= self.len()
+            && pattern
+                .iter()
+                .enumerate()
+                .all(|(i, b)| self.get(pos + i) == Some(*b))
+    }
+
+    // First match at or after from, without wrapping
+    pub fn find_next(&self, from: usize, pattern: &[u8]) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        (from..self.len()).find(|pos| self.matches_at(*pos, pattern))
+    }
+
+    // First match at or after from, wrapping around to the top
+    pub fn find_forward(&self, from: usize, pattern: &[u8]) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        (from..self.len())
+            .chain(0..from)
+            .find(|pos| self.matches_at(*pos, pattern))
+    }
+
+    // Last match strictly before from, wrapping around to the bottom
+    pub fn find_backward(&self, from: usize, pattern: &[u8]) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        (0..from)
+            .rev()
+            .chain((from..self.len()).rev())
+            .find(|pos| self.matches_at(*pos, pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn has(doc: &GapBuffer, s: &str) -> bool {
+        doc.bytes().eq(s.bytes())
+    }
+
+    fn put(doc: &mut GapBuffer, pos: usize, s: &str) {
+        for (i, b) in s.bytes().enumerate() {
+            assert!(doc.insert(pos + i, b));
+        }
+    }
+
+    #[test]
+    fn edits_anywhere_move_the_gap() {
+        let mut doc = GapBuffer::default();
+        put(&mut doc, 0, "held");
+        put(&mut doc, 2, "l wor");
+        put(&mut doc, 0, "!");
+        assert!(has(&doc, "!hel world"));
+        assert_eq!(doc.remove(0), Some(b'!'));
+        assert_eq!(doc.remove(9), None);
+        assert_eq!(doc.get(8), Some(b'd'));
+        assert_eq!(doc.get(9), None);
+        assert!(!doc.insert(11, b'x'));
+    }
+
+    #[test]
+    fn full_buffer_refuses_more() {
+        let mut doc = GapBuffer::default();
+        for i in 0..MAX_FILE_BYTES {
+            assert!(doc.insert(i / 2, b'a'));
+        }
+        assert!(doc.is_full());
+        assert_eq!(doc.free(), 0);
+        let version = doc.version();
+        assert!(!doc.insert(0, b'b'));
+        assert_eq!(doc.version(), version);
+
+        assert_eq!(doc.remove(MAX_FILE_BYTES - 1), Some(b'a'));
+        assert!(doc.insert(0, b'b'));
+        assert_eq!(doc.get(0), Some(b'b'));
+        assert_eq!(doc.len(), MAX_FILE_BYTES);
+    }
+
+    #[test]
+    fn lines_are_found_again_after_a_change() {
+        let mut doc = GapBuffer::default();
+        put(&mut doc, 0, "one\ntwo\nthree");
+        assert_eq!(doc.line_count(), 3);
+        assert_eq!(doc.line_start(2), Some(8));
+        assert_eq!(doc.line_start(3), None);
+        assert_eq!(doc.line_col(9), (2, 1));
+        assert_eq!(doc.line_end(4), 7);
+
+        put(&mut doc, 0, "zero\n");
+        assert_eq!(doc.line_count(), 4);
+        assert_eq!(doc.line_start(3), Some(13));
+        assert_eq!(doc.line_col(9), (2, 0));
+    }
+
+    #[test]
+    fn lines_past_the_kept_ones_are_scanned_for() {
+        let mut doc = GapBuffer::default();
+        let lines = MAX_LINE_STARTS + 10;
+        for i in 0..lines {
+            assert!(doc.insert(2 * i, b'x'));
+            assert!(doc.insert(2 * i + 1, b'\n'));
+        }
+        assert_eq!(doc.line_count(), lines + 1);
+        assert_eq!(doc.line_start(lines - 1), Some(2 * (lines - 1)));
+        assert_eq!(doc.line_col(2 * (lines - 1) + 1), (lines - 1, 1));
+        assert_eq!(doc.line_start(lines + 1), None);
+    }
+}
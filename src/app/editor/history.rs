@@ -0,0 +1,242 @@
+// A fixed-size journal of edits for undo and redo. It is a ring buffer,
+// so once it fills up the oldest edits are forgotten rather than
+// refusing new ones. A group is only ever forgotten whole, so undo never
+// puts back half of a paste, and a group too big to fit at all can't be
+// undone.
+
+const HISTORY_LEN: usize = 256;
+
+// Every edit remembers the document offset it happened at,
+// which is enough to both undo and redo it.
+#[derive(Clone, Copy)]
+pub enum Edit {
+    // c was typed at offset at
+    Insert { at: usize, c: u8 },
+    // c was removed from offset at by a backspace
+    Delete { at: usize, c: u8 },
+    // Enter at offset at moved the rest of the line down
+    Split { at: usize },
+    // Backspace removed the line break at offset at
+    Join { at: usize },
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub edit: Edit,
+    // true if this entry is undone together with the one before it
+    pub continues: bool,
+    // part of a begin_group group rather than a run of typing
+    grouped: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Empty,
+    Started,
+    // lost its first entries to make room, so the rest aren't kept either
+    TooBig,
+}
+
+pub struct History {
+    entries: [Entry; HISTORY_LEN],
+    start: usize,
+    // entries [0, undo_len) can be undone, [undo_len, redo_len) redone
+    undo_len: usize,
+    redo_len: usize,
+    sealed: bool,
+    // Some while a group is open
+    group: Option<Group>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            entries: [Entry {
+                edit: Edit::Split { at: 0 },
+                continues: false,
+                grouped: false,
+            }; HISTORY_LEN],
+            start: 0,
+            undo_len: 0,
+            redo_len: 0,
+            sealed: true,
+            group: None,
+        }
+    }
+}
+
+impl History {
+    fn slot(&self, i: usize) -> usize {
+        (self.start + i) % HISTORY_LEN
+    }
+
+    pub fn record(&mut self, edit: Edit) {
+        // Typing a run of characters is undone as one step
+        let continues = match self.group {
+            Some(Group::TooBig) => return,
+            Some(group) => group == Group::Started,
+            None => {
+                !self.sealed
+                    && match (self.last(), edit) {
+                        (Some(Edit::Insert { at, .. }), Edit::Insert { at: new_at, .. }) => {
+                            at + 1 == new_at
+                        }
+                        _ => false,
+                    }
+            }
+        };
+        // make room first, so a group that hasn't started yet
+        // can't be taken for one that lost its beginning
+        if self.undo_len == HISTORY_LEN {
+            self.forget_oldest();
+            if self.group == Some(Group::TooBig) {
+                return;
+            }
+        }
+        let grouped = self.group.is_some();
+        if grouped {
+            self.group = Some(Group::Started);
+        }
+        let slot = self.slot(self.undo_len);
+        self.entries[slot] = Entry {
+            edit,
+            continues: continues && self.undo_len > 0,
+            grouped,
+        };
+        self.undo_len += 1;
+        self.redo_len = self.undo_len;
+        self.sealed = !matches!(edit, Edit::Insert { .. });
+    }
+
+    // Makes room by dropping the oldest step. A run of typing can lose its
+    // first letters and still be undone, but a group has to go all at once,
+    // and if that's the open group, the rest of it isn't recorded either.
+    fn forget_oldest(&mut self) {
+        loop {
+            self.start = self.slot(1);
+            self.undo_len -= 1;
+            self.redo_len -= 1;
+            if self.undo_len == 0 {
+                if self.group == Some(Group::Started) {
+                    self.group = Some(Group::TooBig);
+                }
+                return;
+            }
+            let head = self.slot(0);
+            let head = &mut self.entries[head];
+            if !head.continues {
+                return;
+            }
+            if !head.grouped {
+                head.continues = false;
+                return;
+            }
+        }
+    }
+
+    // Ends the current run of typing, so the next insert starts a new undo step.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    // Everything recorded until end_group is undone as a single step
+    pub fn begin_group(&mut self) {
+        self.group = Some(Group::Empty);
+    }
+
+    pub fn end_group(&mut self) {
+        self.group = None;
+        self.sealed = true;
+    }
+
+    fn last(&self) -> Option<Edit> {
+        if self.undo_len == 0 {
+            None
+        } else {
+            Some(self.entries[self.slot(self.undo_len - 1)].edit)
+        }
+    }
+
+    pub fn undo(&mut self) -> Option<Entry> {
+        if self.undo_len == 0 {
+            return None;
+        }
+        self.undo_len -= 1;
+        self.sealed = true;
+        Some(self.entries[self.slot(self.undo_len)])
+    }
+
+    pub fn redo(&mut self) -> Option<Entry> {
+        if self.undo_len == self.redo_len {
+            return None;
+        }
+        let entry = self.entries[self.slot(self.undo_len)];
+        self.undo_len += 1;
+        self.sealed = true;
+        Some(entry)
+    }
+
+    // true if the next redo belongs to the step we just redid
+    pub fn redo_continues(&self) -> bool {
+        self.undo_len < self.redo_len && self.entries[self.slot(self.undo_len)].continues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paste(history: &mut History, at: usize, text: &[u8]) {
+        history.begin_group();
+        for (i, &c) in text.iter().enumerate() {
+            history.record(Edit::Insert { at: at + i, c });
+        }
+        history.end_group();
+    }
+
+    #[test]
+    fn typing_is_undone_a_run_at_a_time() {
+        let mut history = History::default();
+        history.record(Edit::Insert { at: 0, c: b'a' });
+        history.record(Edit::Insert { at: 1, c: b'b' });
+        history.record(Edit::Split { at: 2 });
+
+        assert!(matches!(history.undo().unwrap().edit, Edit::Split { at: 2 }));
+        let b = history.undo().unwrap();
+        assert!(matches!(b.edit, Edit::Insert { at: 1, c: b'b' }));
+        assert!(b.continues);
+        assert!(!history.undo().unwrap().continues);
+        assert!(history.undo().is_none());
+
+        assert!(history.redo().is_some());
+        assert!(history.redo_continues());
+    }
+
+    #[test]
+    fn paste_after_a_full_paste_can_be_undone() {
+        let mut history = History::default();
+        paste(&mut history, 0, &[b'x'; HISTORY_LEN]);
+        paste(&mut history, HISTORY_LEN, b"ab");
+
+        let b = history.undo().unwrap();
+        assert!(matches!(b.edit, Edit::Insert { at: 257, c: b'b' }));
+        assert!(b.continues);
+        let a = history.undo().unwrap();
+        assert!(matches!(a.edit, Edit::Insert { at: 256, c: b'a' }));
+        assert!(!a.continues);
+        // the whole first paste went to make room
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn paste_too_big_to_keep_isnt_kept_at_all() {
+        let mut history = History::default();
+        history.record(Edit::Insert { at: 0, c: b'a' });
+        paste(&mut history, 1, &[b'x'; HISTORY_LEN + 1]);
+        assert!(history.undo().is_none());
+
+        // and the next edit is recorded as usual
+        history.record(Edit::Join { at: 3 });
+        assert!(matches!(history.undo().unwrap().edit, Edit::Join { at: 3 }));
+    }
+}
@@ -0,0 +1,117 @@
+use super::TextEditor;
+
+// How many spaces one level of indentation is, same as the example programs
+const INDENT: usize = 4;
+
+// Spaces to take off an indent of width to line up with the level before it
+fn dedent(width: usize) -> usize {
+    if width == 0 {
+        0
+    } else {
+        (width - 1) % INDENT + 1
+    }
+}
+
+impl TextEditor {
+    // leading spaces of the line starting at offset start
+    fn indent_of(&self, start: usize) -> usize {
+        let mut n = 0;
+        while self.doc.get(start + n) == Some(b' ') {
+            n += 1;
+        }
+        n
+    }
+
+    // Enter keeps the indentation of the line it splits,
+    // plus one more level right after a '{'
+    pub(super) fn auto_newline(&mut self) {
+        let (_, col) = self.doc.line_col(self.cursor);
+        let start = self.cursor - col;
+        let mut indent = usize::min(self.indent_of(start), col);
+
+        let mut last = self.cursor;
+        while last > start && self.doc.get(last - 1) == Some(b' ') {
+            last -= 1;
+        }
+        if last > start && self.doc.get(last - 1) == Some(b'{') {
+            indent += INDENT;
+        }
+
+        self.history.begin_group();
+        self.edit_insert(b'\n');
+        for _ in 0..indent {
+            self.edit_insert(b' ');
+        }
+        self.history.end_group();
+    }
+
+    // A '}' typed with nothing but indentation before it goes back a level
+    pub(super) fn close_brace(&mut self) {
+        let (_, col) = self.doc.line_col(self.cursor);
+        let start = self.cursor - col;
+
+        self.history.begin_group();
+        if self.indent_of(start) >= col {
+            for _ in 0..dedent(col) {
+                self.edit_backspace();
+            }
+        }
+        self.edit_insert(b'}');
+        self.history.end_group();
+    }
+
+    // Tab and Shift+Tab move the cursor's line, or every line
+    // the selection touches, one level in or out
+    pub fn indent(&mut self, outdent: bool) {
+        if self.is_prompting() {
+            return;
+        }
+        let (cursor_line, cursor_col) = self.doc.line_col(self.cursor);
+        let anchor = self.anchor.map(|a| self.doc.line_col(usize::min(a, self.doc.len())));
+        let (first, last) = match anchor {
+            Some((line, _)) => (usize::min(line, cursor_line), usize::max(line, cursor_line)),
+            None => (cursor_line, cursor_line),
+        };
+
+        // Going from the bottom up means the lines still
+        // to do never move while we change the ones below them
+        let mut cursor_shift = 0;
+        let mut anchor_shift = 0;
+        self.history.begin_group();
+        for line in (first..=last).rev() {
+            let Some(start) = self.doc.line_start(line) else {
+                continue;
+            };
+            let shift = if outdent {
+                let n = dedent(self.indent_of(start));
+                self.cursor = start + n;
+                for _ in 0..n {
+                    self.edit_backspace();
+                }
+                -(n as isize)
+            } else {
+                self.cursor = start;
+                for _ in 0..INDENT {
+                    self.edit_insert(b' ');
+                }
+                INDENT as isize
+            };
+            if line == cursor_line {
+                cursor_shift = shift;
+            }
+            if anchor.is_some_and(|(l, _)| l == line) {
+                anchor_shift = shift;
+            }
+        }
+        self.history.end_group();
+
+        self.cursor = self.shifted(cursor_line, cursor_col, cursor_shift);
+        self.anchor = anchor.map(|(line, col)| self.shifted(line, col, anchor_shift));
+    }
+
+    // Where (line, col) ended up after its line moved by shift columns
+    fn shifted(&self, line: usize, col: usize, shift: isize) -> usize {
+        let start = self.doc.line_start(line).unwrap_or(0);
+        start + usize::min(col.saturating_add_signed(shift), self.line_len(start))
+    }
+}
@@ -0,0 +1,656 @@
+use core::fmt::Write;
+use simple_interp::ArrayString;
+
+use crate::{
+    surface::Surface,
+    vga::{Color, ColorCode},
+    FsType, MAX_PATH_BYTES,
+};
+
+use super::{clipboard::Clipboard, prompt::Prompt, window::Window};
+use file::NamePrompt;
+pub(crate) use file::SAVE_FILE;
+use gap::GapBuffer;
+use history::{Edit, History};
+use replace::Replace;
+use syntax::{Brackets, Lexer};
+use vi::Vi;
+use wrap::Wrap;
+
+mod file;
+mod gap;
+mod history;
+mod indent;
+mod motion;
+mod replace;
+mod select;
+mod syntax;
+mod vi;
+mod wrap;
+
+// What typing does right now. Anything other than Edit
+// takes over the title bar to show a prompt.
+enum Mode {
+    Edit,
+    Search {
+        query: Prompt<32>,
+        // where the cursor was before the search, for Esc to go back to
+        origin: usize,
+        found: bool,
+    },
+    Replace(Replace),
+    // Ctrl+G, waiting for a line number
+    GoTo(Prompt<8>),
+    SaveAs(NamePrompt),
+    Open(NamePrompt),
+    // F6 with unsaved changes, waiting for s, d or c
+    ConfirmExit,
+}
+
+pub struct TextEditor {
+    mode: Mode,
+    doc: GapBuffer,
+    // byte offset into doc, so it can sit anywhere from 0 to doc.len()
+    cursor: usize,
+    // the other end of the selection, if there is one
+    anchor: Option<usize>,
+    // first line drawn at the top of the window
+    scroll: usize,
+    wrap: Wrap,
+    // first column drawn when lines aren't wrapped
+    hscroll: usize,
+    history: History,
+    brackets: Brackets,
+    // a message for the status bar, picked up by SwimInterface
+    status: Option<ArrayString<64>>,
+    // why the last save failed, shown in the title
+    error: Option<ArrayString<64>>,
+    // set by anything that writes a file, so the Explorers can catch up
+    files_changed: bool,
+    // doc.version() when the file was loaded, to tell if it has changed since
+    saved: usize,
+    show_gutter: bool,
+    show_status_line: bool,
+    // F10, vi keys instead of plain typing
+    vi: Option<Vi>,
+    // the part of the window the text goes in, after the
+    // gutter and status line have taken their share
+    text: Window,
+    pub window: Window,
+    pub filename: ArrayString<MAX_PATH_BYTES>,
+}
+
+impl TextEditor {
+    pub fn new(window: Window, filename: ArrayString<MAX_PATH_BYTES>) -> Self {
+        Self {
+            mode: Mode::Edit,
+            doc: GapBuffer::default(),
+            cursor: 0,
+            anchor: None,
+            scroll: 0,
+            wrap: Wrap::Soft,
+            hscroll: 0,
+            history: History::default(),
+            brackets: Brackets::default(),
+            status: None,
+            error: None,
+            files_changed: false,
+            saved: 0,
+            show_gutter: false,
+            show_status_line: false,
+            vi: None,
+            text: window.clone(),
+            window,
+            filename,
+        }
+    }
+
+    pub fn title(&self) -> ArrayString<64> {
+        let mut a = ArrayString::<64>::default();
+        match &self.mode {
+            Mode::Edit => {
+                let name = self.filename.as_str().unwrap_or("INVALID_NAME");
+                let _ = match (&self.error, &self.vi) {
+                    (Some(error), _) => write!(a, "SAVE FAILED:{}", error.as_str().unwrap_or("")),
+                    (None, Some(vi)) => vi.write_title(&mut a, name, self.is_modified()),
+                    (None, None) => write!(
+                        a,
+                        "EDIT:{}{},F6 to exit",
+                        name,
+                        if self.is_modified() { "*" } else { "" }
+                    ),
+                };
+            }
+            Mode::Search { query, found, .. } => {
+                let _ = write!(
+                    a,
+                    "FIND{}:{}",
+                    if *found || query.is_empty() {
+                        ""
+                    } else {
+                        "(none)"
+                    },
+                    query.as_str()
+                );
+            }
+            Mode::Replace(replace) => a = replace.title(),
+            Mode::GoTo(line) => {
+                let _ = write!(a, "GO TO LINE:{}", line.as_str());
+            }
+            Mode::SaveAs(prompt) => a = prompt.title("SAVE AS"),
+            Mode::Open(prompt) => a = prompt.title("OPEN"),
+            Mode::ConfirmExit => {
+                let _ = write!(a, "(s)ave,(d)iscard,(c)ancel?");
+            }
+        }
+        a
+    }
+
+    pub fn take_status(&mut self) -> Option<ArrayString<64>> {
+        self.status.take()
+    }
+
+    pub fn take_files_changed(&mut self) -> bool {
+        core::mem::take(&mut self.files_changed)
+    }
+
+    pub fn is_prompting(&self) -> bool {
+        !matches!(self.mode, Mode::Edit)
+    }
+
+    pub fn dump(&self, buf: &mut [u8]) -> usize {
+        self.doc.copy_to(buf)
+    }
+
+    // Fills the editor with the contents of a file without recording
+    // any of it, since loading a file is not something to undo.
+    pub fn load(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            if self.doc.insert(self.cursor, *b) {
+                self.cursor += 1;
+            }
+        }
+        self.saved = self.doc.version();
+    }
+
+    pub fn toggle_gutter(&mut self) {
+        self.show_gutter = !self.show_gutter;
+        self.layout();
+    }
+
+    pub fn toggle_status_line(&mut self) {
+        self.show_status_line = !self.show_status_line;
+        self.layout();
+    }
+
+    fn layout(&mut self) {
+        let gutter = if self.show_gutter {
+            self.gutter_width()
+        } else {
+            0
+        };
+        self.text = Window::new(
+            self.window.x1 as usize + gutter,
+            self.window.y1 as usize,
+            self.window.width() - gutter,
+            self.window.height() - usize::from(self.show_status_line),
+        );
+    }
+
+    // room for the biggest line number and a space after it
+    fn gutter_width(&self) -> usize {
+        let mut digits = 1;
+        let mut lines = self.doc.line_count();
+        while lines >= 10 {
+            lines /= 10;
+            digits += 1;
+        }
+        usize::max(digits, 2) + 1
+    }
+
+    // true if this key means the editor is done and should close
+    pub fn insert_char(&mut self, c: char, fs: &mut FsType, clipboard: &mut Clipboard) -> bool {
+        if self.vi_commands() {
+            return self.vi_key(c, fs, clipboard);
+        }
+        match &mut self.mode {
+            Mode::Edit => {
+                self.vi_record(c);
+                self.delete_selection();
+                if c == '}' {
+                    self.close_brace()
+                } else {
+                    self.edit_insert(c as u8)
+                }
+            }
+            Mode::Search { query, origin, .. } => {
+                query.push(c);
+                let origin = *origin;
+                self.search(origin, true);
+            }
+            Mode::Replace(_) => self.replace_char(c),
+            Mode::GoTo(line) => {
+                if c.is_ascii_digit() {
+                    line.push(c);
+                }
+            }
+            Mode::SaveAs(_) | Mode::Open(_) => self.name_char(c),
+            Mode::ConfirmExit => return self.confirm_exit(c, fs),
+        }
+        false
+    }
+
+    // true if this was a vi :q and the editor should close
+    pub fn newline(&mut self, fs: &mut FsType) -> bool {
+        if self.vi_commands() {
+            return self.vi_newline(fs);
+        }
+        match self.mode {
+            Mode::Edit => {
+                self.vi_record('\n');
+                self.delete_selection();
+                self.auto_newline()
+            }
+            Mode::Search { .. } => self.search(self.cursor + 1, true),
+            Mode::Replace(_) => self.replace_newline(),
+            Mode::GoTo(line) => {
+                self.mode = Mode::Edit;
+                if let Ok(line) = line.as_str().parse() {
+                    self.go_to_line(line);
+                }
+            }
+            Mode::SaveAs(_) | Mode::Open(_) => self.name_newline(fs),
+            Mode::ConfirmExit => {}
+        }
+        false
+    }
+
+    // Shift+Enter, which only means something different while searching
+    pub fn shift_newline(&mut self, fs: &mut FsType) -> bool {
+        if let Mode::Search { .. } = self.mode {
+            self.search(self.cursor, false);
+            false
+        } else {
+            self.newline(fs)
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.vi_commands() {
+            return self.vi_backspace();
+        }
+        match &mut self.mode {
+            Mode::Edit => {
+                self.vi_record('\x08');
+                if !self.delete_selection() {
+                    self.edit_backspace()
+                }
+            }
+            Mode::Search { query, origin, .. } => {
+                query.pop();
+                let origin = *origin;
+                self.search(origin, true);
+            }
+            Mode::Replace(_) => self.replace_backspace(),
+            Mode::GoTo(line) => {
+                line.pop();
+            }
+            Mode::SaveAs(_) | Mode::Open(_) => self.name_backspace(),
+            Mode::ConfirmExit => {}
+        }
+    }
+
+    // Every change to the document goes through edit_insert and
+    // edit_backspace, so all of them can be undone.
+    fn edit_insert(&mut self, c: u8) {
+        let at = self.cursor;
+        if self.doc.insert(at, c) {
+            self.cursor += 1;
+            self.history.record(if c == b'\n' {
+                Edit::Split { at }
+            } else {
+                Edit::Insert { at, c }
+            });
+        }
+    }
+
+    fn edit_backspace(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        let at = self.cursor - 1;
+        if let Some(c) = self.doc.remove(at) {
+            self.cursor = at;
+            self.history.record(if c == b'\n' {
+                Edit::Join { at }
+            } else {
+                Edit::Delete { at, c }
+            });
+        }
+    }
+
+    pub fn find(&mut self) {
+        self.mode = Mode::Search {
+            query: Prompt::default(),
+            origin: self.cursor,
+            found: false,
+        };
+    }
+
+    pub fn escape(&mut self) {
+        if matches!(self.mode, Mode::Edit) && self.vi_escape() {
+            return;
+        }
+        match self.mode {
+            Mode::Edit => {
+                self.anchor = None;
+                self.error = None;
+            }
+            Mode::Search { origin, .. } => {
+                self.cursor = origin;
+                self.mode = Mode::Edit;
+            }
+            Mode::Replace(_) => self.finish_replace(),
+            Mode::GoTo(_) | Mode::SaveAs(_) | Mode::Open(_) | Mode::ConfirmExit => {
+                self.mode = Mode::Edit
+            }
+        }
+    }
+
+    // Moves the cursor to the next (or previous) match of the query,
+    // starting from the given offset. The cursor stays put if nothing matches.
+    fn search(&mut self, from: usize, forward: bool) {
+        let Mode::Search {
+            query,
+            origin,
+            found,
+        } = &mut self.mode
+        else {
+            return;
+        };
+        let hit = if forward {
+            self.doc.find_forward(from, query.as_bytes())
+        } else {
+            self.doc.find_backward(from, query.as_bytes())
+        };
+        *found = hit.is_some();
+        self.cursor = match hit {
+            Some(pos) => pos,
+            None if query.is_empty() => *origin,
+            None => self.cursor,
+        };
+    }
+
+    // Moving leaves the search with the cursor on the current match
+    fn accept_search(&mut self) {
+        if let Mode::Search { .. } = self.mode {
+            self.mode = Mode::Edit;
+        }
+    }
+
+    pub fn undo(&mut self) {
+        self.anchor = None;
+        while let Some(entry) = self.history.undo() {
+            match entry.edit {
+                Edit::Insert { at, .. } | Edit::Split { at } => {
+                    self.doc.remove(at);
+                    self.cursor = at;
+                }
+                Edit::Delete { at, c } => {
+                    self.doc.insert(at, c);
+                    self.cursor = at + 1;
+                }
+                Edit::Join { at } => {
+                    self.doc.insert(at, b'\n');
+                    self.cursor = at + 1;
+                }
+            }
+            if !entry.continues {
+                break;
+            }
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.anchor = None;
+        while let Some(entry) = self.history.redo() {
+            match entry.edit {
+                Edit::Insert { at, c } => {
+                    self.doc.insert(at, c);
+                    self.cursor = at + 1;
+                }
+                Edit::Split { at } => {
+                    self.doc.insert(at, b'\n');
+                    self.cursor = at + 1;
+                }
+                Edit::Delete { at, .. } | Edit::Join { at } => {
+                    self.doc.remove(at);
+                    self.cursor = at;
+                }
+            }
+            if !self.history.redo_continues() {
+                break;
+            }
+        }
+    }
+
+    fn line_len(&self, start: usize) -> usize {
+        self.doc.line_end(start) - start
+    }
+
+    fn keep_cursor_on_screen(&mut self) {
+        let (cursor_line, _) = self.doc.line_col(self.cursor);
+        let mut total = 0;
+        let mut start = self.doc.line_start(self.scroll).unwrap_or(0);
+        for line in self.scroll..=cursor_line {
+            total += if line == cursor_line {
+                self.row_of(self.cursor).1 + 1
+            } else {
+                self.rows_in_line(start)
+            };
+            start = self.doc.line_end(start) + 1;
+        }
+
+        if total == 0 {
+            self.scroll = cursor_line;
+        } else if total >= self.text.height() {
+            self.scroll += total - self.text.height()
+        }
+        self.follow_cursor_sideways();
+    }
+
+    // Draws line number line, beginning at offset start, returning how many rows it took
+    fn drawline(
+        &self,
+        surface: &mut impl Surface,
+        line: usize,
+        start: usize,
+        y_base: usize,
+    ) -> usize {
+        let gray_inv = ColorCode::new(Color::Black, Color::LightGray);
+        let found = ColorCode::new(Color::Black, Color::Yellow);
+
+        let query = match &self.mode {
+            Mode::Edit | Mode::GoTo(_) | Mode::SaveAs(_) | Mode::Open(_) | Mode::ConfirmExit => {
+                &[]
+            }
+            Mode::Search { query, .. } => query.as_bytes(),
+            Mode::Replace(replace) => replace.highlight(),
+        };
+        // one past the last byte of the match we are currently inside of
+        let mut match_end = 0;
+        let (sel_start, sel_end) = self.selection().unwrap_or((0, 0));
+        let matched = self.brackets.matched();
+
+        let end = self.doc.line_end(start);
+        let mut tokens = Lexer::new(&self.doc, start, end);
+        let mut token = tokens.next();
+
+        let mut rows = 0;
+        let mut row = Some(start);
+        while let Some(row_start) = row {
+            let y = y_base + rows;
+            if y >= self.text.height() {
+                break;
+            }
+            let next = self.next_row(row_start);
+            let row_end = next.unwrap_or(end);
+            self.draw_gutter(surface, if rows == 0 { Some(line) } else { None }, y);
+
+            for x in 0..self.text.width() {
+                let pos = row_start + self.hscroll + x;
+                let in_row = pos < row_end;
+                let c = if in_row {
+                    self.doc.get(pos).unwrap_or(b' ') as char
+                } else {
+                    ' '
+                };
+
+                if !query.is_empty() && in_row && self.doc.matches_at(pos, query) {
+                    match_end = pos + query.len();
+                }
+                while token.is_some_and(|t| t.end <= pos) {
+                    token = tokens.next();
+                }
+                let syntax = match token {
+                    Some(t) if self.brackets.is_unmatched(t.start) => syntax::unmatched_color(),
+                    Some(t) => syntax::color(t.kind),
+                    None => syntax::color(syntax::Kind::Plain),
+                };
+                self.text.plot(
+                    surface,
+                    c,
+                    x as u8,
+                    y as u8,
+                    // the cursor can sit just past the end of the last row
+                    if (pos == self.cursor && (in_row || (next.is_none() && pos == end)))
+                        || (in_row && sel_start <= pos && pos < sel_end)
+                    {
+                        gray_inv
+                    } else if in_row && pos < match_end {
+                        found
+                    } else if in_row && matched == Some(pos) {
+                        syntax::matched_color()
+                    } else {
+                        syntax
+                    },
+                );
+            }
+
+            rows += 1;
+            row = next;
+        }
+
+        rows
+    }
+
+    // The line number goes on the first row of a line and
+    // the rows it wraps onto are left blank
+    fn draw_gutter(&self, surface: &mut impl Surface, line: Option<usize>, y: usize) {
+        let gutter = self.text.x1 - self.window.x1;
+        if gutter == 0 {
+            return;
+        }
+        let color = ColorCode::new(Color::DarkGray, Color::Black);
+        let mut number = ArrayString::<8>::default();
+        if let Some(line) = line {
+            let _ = write!(number, "{:>1$}", line + 1, gutter as usize - 1);
+        }
+        let mut digits = number.as_str().unwrap_or("").chars();
+        for col in 0..gutter {
+            let c = digits.next().unwrap_or(' ');
+            self.window.plot(surface, c, col, y as u8, color);
+        }
+    }
+
+    fn draw_status_line(&self, surface: &mut impl Surface) {
+        let color = ColorCode::new(Color::Black, Color::LightGray);
+        let (line, col) = self.doc.line_col(self.cursor);
+        let mut a = ArrayString::<64>::default();
+        let _ = write!(
+            a,
+            "{}:{}  {} bytes{}",
+            line + 1,
+            col + 1,
+            self.doc.len(),
+            if self.is_modified() { "  modified" } else { "" }
+        );
+        let mut text = a.as_str().unwrap_or("").chars();
+        let y = self.window.height() - 1;
+        for col in 0..self.window.width() {
+            let c = text.next().unwrap_or(' ');
+            self.window.plot(surface, c, col as u8, y as u8, color);
+        }
+    }
+
+    fn clear_y(&self, surface: &mut impl Surface, y: u8) {
+        let gray = ColorCode::new(Color::LightGray, Color::Black);
+        for col in 0..self.window.width() {
+            self.window.plot(surface, ' ', col as u8, y, gray);
+        }
+    }
+
+    pub fn draw(&mut self, surface: &mut impl Surface) {
+        // the gutter grows with the number of lines
+        self.layout();
+        self.keep_cursor_on_screen();
+        self.brackets.refresh(&self.doc);
+        self.brackets.find_match(&self.doc, self.cursor);
+
+        let mut used = 0;
+        let mut line = self.scroll;
+        let mut start = self.doc.line_start(self.scroll);
+
+        while used < self.text.height() {
+            let Some(at) = start else { break };
+            used += self.drawline(surface, line, at, used);
+            let end = self.doc.line_end(at);
+            line += 1;
+            start = if end < self.doc.len() {
+                Some(end + 1)
+            } else {
+                None
+            };
+        }
+        while used < self.text.height() {
+            self.clear_y(surface, used as u8);
+            used += 1;
+        }
+        if self.show_status_line {
+            self.draw_status_line(surface);
+        }
+    }
+
+    pub fn arrow_left(&mut self, select: bool) {
+        self.before_motion(select);
+        if self.cursor > 0 {
+            self.cursor -= 1
+        }
+    }
+
+    pub fn arrow_right(&mut self, select: bool) {
+        self.before_motion(select);
+        if self.cursor < self.doc.len() {
+            self.cursor += 1
+        }
+    }
+
+    pub fn arrow_up(&mut self, select: bool) {
+        self.before_motion(select);
+        let (line, col) = self.doc.line_col(self.cursor);
+        if line == 0 {
+            return;
+        }
+        if let Some(start) = self.doc.line_start(line - 1) {
+            self.cursor = start + usize::min(col, self.line_len(start))
+        }
+    }
+
+    pub fn arrow_down(&mut self, select: bool) {
+        self.before_motion(select);
+        let (line, col) = self.doc.line_col(self.cursor);
+        if let Some(start) = self.doc.line_start(line + 1) {
+            self.cursor = start + usize::min(col, self.line_len(start))
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use super::{Mode, TextEditor};
+use crate::app::prompt::Prompt;
+
+// Cursor motions other than the four arrows. Like the arrows, all of them
+// take select to say whether Shift was held, and stop a run of typing
+// from being undone together with whatever is typed after the motion.
+impl TextEditor {
+    pub(super) fn before_motion(&mut self, select: bool) {
+        self.accept_search();
+        self.history.seal();
+        self.start_selection(select);
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.before_motion(select);
+        let (_, col) = self.doc.line_col(self.cursor);
+        self.cursor -= col;
+    }
+
+    pub fn end(&mut self, select: bool) {
+        self.before_motion(select);
+        self.cursor = self.doc.line_end(self.cursor);
+    }
+
+    pub fn go_to(&mut self) {
+        self.mode = Mode::GoTo(Prompt::default());
+    }
+
+    // Puts the cursor at the start of a line, counting from 1 like
+    // the gutter does. Past the end means the last line.
+    pub fn go_to_line(&mut self, line: usize) {
+        self.before_motion(false);
+        let line = usize::min(line.saturating_sub(1), self.doc.line_count() - 1);
+        self.cursor = self.doc.line_start(line).unwrap_or(0);
+    }
+
+    // Paging moves by whole rows on screen rather than lines,
+    // so a long wrapped line doesn't make a page jump too far.
+    pub fn page_up(&mut self, select: bool) {
+        self.before_motion(select);
+        for _ in 0..self.text.height() {
+            self.row_up();
+        }
+    }
+
+    pub fn page_down(&mut self, select: bool) {
+        self.before_motion(select);
+        for _ in 0..self.text.height() {
+            self.row_down();
+        }
+    }
+
+    // Up one row on screen, which may still be in the same wrapped line
+    fn row_up(&mut self) {
+        let (row, _) = self.row_of(self.cursor);
+        if row == 0 {
+            return;
+        }
+        // Just before this row is either the end of the row above it in the same
+        // line, or the '\n' of the line above, which is on that line's last row
+        let x = self.cursor - row;
+        let (prev, _) = self.row_of(row - 1);
+        self.cursor = usize::min(prev + x, self.row_last(prev));
+    }
+
+    fn row_down(&mut self) {
+        let (row, _) = self.row_of(self.cursor);
+        let x = self.cursor - row;
+        let next = match self.next_row(row) {
+            Some(next) => next,
+            None => {
+                let end = self.doc.line_end(row);
+                if end == self.doc.len() {
+                    return;
+                }
+                end + 1
+            }
+        };
+        self.cursor = usize::min(next + x, self.row_last(next));
+    }
+
+    // Deletes the character after the cursor, going through the same
+    // path as Backspace so it can be undone.
+    pub fn delete(&mut self) {
+        if !matches!(self.mode, Mode::Edit) {
+            return;
+        }
+        if !self.delete_selection() && self.cursor < self.doc.len() {
+            self.cursor += 1;
+            self.edit_backspace();
+        }
+    }
+
+    pub fn word_left(&mut self, select: bool) {
+        self.before_motion(select);
+        while self.cursor > 0 && !self.is_word(self.cursor - 1) {
+            self.cursor -= 1;
+        }
+        while self.cursor > 0 && self.is_word(self.cursor - 1) {
+            self.cursor -= 1;
+        }
+    }
+
+    pub fn word_right(&mut self, select: bool) {
+        self.before_motion(select);
+        while self.cursor < self.doc.len() && self.is_word(self.cursor) {
+            self.cursor += 1;
+        }
+        while self.cursor < self.doc.len() && !self.is_word(self.cursor) {
+            self.cursor += 1;
+        }
+    }
+
+    pub(super) fn is_word(&self, pos: usize) -> bool {
+        self.doc
+            .get(pos)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_')
+    }
+}
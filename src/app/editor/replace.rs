@@ -0,0 +1,182 @@
+use core::fmt::Write;
+use simple_interp::ArrayString;
+
+use super::{Mode, TextEditor};
+use crate::app::prompt::Prompt;
+
+// Replacing goes through three prompts in the title bar: what to look for,
+// what to put there instead, and then y/n/a/q for every match in turn.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Pattern,
+    Replacement,
+    Confirm,
+}
+
+pub struct Replace {
+    pattern: Prompt<32>,
+    replacement: Prompt<32>,
+    stage: Stage,
+    count: usize,
+}
+
+impl Replace {
+    pub fn title(&self) -> ArrayString<64> {
+        let mut a = ArrayString::<64>::default();
+        let _ = match self.stage {
+            Stage::Pattern => write!(a, "REPLACE:{}", self.pattern.as_str()),
+            Stage::Replacement => write!(
+                a,
+                "REPLACE {} WITH:{}",
+                self.pattern.as_str(),
+                self.replacement.as_str()
+            ),
+            Stage::Confirm => write!(a, "REPLACE? (y/n/a/q)"),
+        };
+        a
+    }
+
+    // The match the cursor is on, once we are asking about it
+    pub fn highlight(&self) -> &[u8] {
+        match self.stage {
+            Stage::Confirm => self.pattern.as_bytes(),
+            _ => &[],
+        }
+    }
+}
+
+impl TextEditor {
+    pub fn replace(&mut self) {
+        self.mode = Mode::Replace(Replace {
+            pattern: Prompt::default(),
+            replacement: Prompt::default(),
+            stage: Stage::Pattern,
+            count: 0,
+        });
+    }
+
+    pub(super) fn replace_char(&mut self, c: char) {
+        let Mode::Replace(r) = &mut self.mode else {
+            return;
+        };
+        match r.stage {
+            Stage::Pattern => {
+                r.pattern.push(c);
+            }
+            Stage::Replacement => {
+                r.replacement.push(c);
+            }
+            Stage::Confirm => match c {
+                'y' if self.replace_one() => {
+                    self.replace_next();
+                }
+                'n' => {
+                    self.cursor += 1;
+                    self.replace_next();
+                }
+                'a' => while self.replace_one() && self.replace_next() {},
+                'q' => self.finish_replace(),
+                _ => {}
+            },
+        }
+    }
+
+    pub(super) fn replace_newline(&mut self) {
+        let Mode::Replace(r) = &mut self.mode else {
+            return;
+        };
+        match r.stage {
+            Stage::Pattern if r.pattern.is_empty() => self.mode = Mode::Edit,
+            Stage::Pattern => r.stage = Stage::Replacement,
+            Stage::Replacement => {
+                // Always start from the top and never wrap around, so
+                // a replacement containing the pattern can't loop forever.
+                r.stage = Stage::Confirm;
+                self.history.seal();
+                self.cursor = 0;
+                self.replace_next();
+            }
+            Stage::Confirm => {}
+        }
+    }
+
+    pub(super) fn replace_backspace(&mut self) {
+        let Mode::Replace(r) = &mut self.mode else {
+            return;
+        };
+        match r.stage {
+            Stage::Pattern => {
+                r.pattern.pop();
+            }
+            Stage::Replacement => {
+                r.replacement.pop();
+            }
+            Stage::Confirm => {}
+        }
+    }
+
+    // Moves the cursor to the next match at or after it, finishing if there are none left
+    fn replace_next(&mut self) -> bool {
+        let Mode::Replace(r) = &self.mode else {
+            return false;
+        };
+        match self.doc.find_next(self.cursor, r.pattern.as_bytes()) {
+            Some(pos) => {
+                self.cursor = pos;
+                true
+            }
+            None => {
+                self.finish_replace();
+                false
+            }
+        }
+    }
+
+    // Swaps the match under the cursor for the replacement, leaving the cursor after it.
+    // This is a backspace over the match and then typing, so undo treats it like any
+    // other edit, just all in one step.
+    fn replace_one(&mut self) -> bool {
+        let Mode::Replace(r) = &self.mode else {
+            return false;
+        };
+        let (pattern, replacement) = (r.pattern, r.replacement);
+        if self.doc.free() + pattern.len() < replacement.len() {
+            self.finish_replace();
+            return false;
+        }
+
+        self.history.begin_group();
+        self.cursor += pattern.len();
+        for _ in 0..pattern.len() {
+            self.edit_backspace();
+        }
+        for c in replacement.as_bytes() {
+            self.edit_insert(*c);
+        }
+        self.history.end_group();
+
+        if let Mode::Replace(r) = &mut self.mode {
+            r.count += 1;
+        }
+        true
+    }
+
+    pub(super) fn finish_replace(&mut self) {
+        if let Mode::Replace(r) = &self.mode {
+            let mut status = ArrayString::<64>::default();
+            let _ = write!(
+                status,
+                "Replaced {} of \"{}\"{}",
+                r.count,
+                r.pattern.as_str(),
+                if self.doc.free() + r.pattern.len() < r.replacement.len() {
+                    ", file is full"
+                } else {
+                    ""
+                }
+            );
+            self.status = Some(status);
+        }
+        self.mode = Mode::Edit;
+    }
+}
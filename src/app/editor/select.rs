@@ -0,0 +1,75 @@
+use super::TextEditor;
+use crate::app::clipboard::Clipboard;
+
+// The first Shift+arrow drops the anchor where the cursor is, and the
+// ones after it drag the cursor end of the selection along. An arrow
+// without Shift lets go of it, and so does cutting, copying or typing over it.
+impl TextEditor {
+    pub(super) fn start_selection(&mut self, select: bool) {
+        if !select {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.cursor);
+        }
+    }
+
+    // (start, end) of the selected bytes, if any are selected
+    pub(super) fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = usize::min(self.anchor?, self.doc.len());
+        let (start, end) = if anchor < self.cursor {
+            (anchor, self.cursor)
+        } else {
+            (self.cursor, anchor)
+        };
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    // Removes the selected text as one undo step, returning false if nothing was selected
+    pub(super) fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.anchor = None;
+        let Some((start, end)) = selection else {
+            return false;
+        };
+
+        self.history.begin_group();
+        self.cursor = end;
+        for _ in start..end {
+            self.edit_backspace();
+        }
+        self.history.end_group();
+        true
+    }
+
+    pub fn copy(&mut self, clipboard: &mut Clipboard) {
+        if let Some((start, end)) = self.selection() {
+            clipboard.set(self.doc.bytes().skip(start).take(end - start));
+            self.anchor = None;
+        }
+    }
+
+    pub fn cut(&mut self, clipboard: &mut Clipboard) {
+        if let Some((start, end)) = self.selection() {
+            clipboard.set(self.doc.bytes().skip(start).take(end - start));
+            self.delete_selection();
+        }
+    }
+
+    // Pastes over the selection, if there is one. Whatever doesn't
+    // fit once the document is as big as a file can be is dropped.
+    pub fn paste(&mut self, clipboard: &Clipboard) {
+        if self.is_prompting() {
+            return;
+        }
+        self.delete_selection();
+        self.history.begin_group();
+        for c in clipboard.as_bytes() {
+            self.edit_insert(*c);
+        }
+        self.history.end_group();
+    }
+}
@@ -0,0 +1,268 @@
+use super::gap::GapBuffer;
+use crate::vga::{Color, ColorCode};
+
+// Coloring for simple_interp programs. The lexer only ever looks at the
+// bytes it is given, so drawing a line lexes just that line. Strings and
+// comments stop at the end of a line, and a '#' runs to the end of the
+// line as a comment.
+const KEYWORDS: [&[u8]; 8] = [
+    b"while", b"if", b"else", b"print", b"input", b"not", b"and", b"or",
+];
+const LITERALS: [&[u8]; 2] = [b"true", b"false"];
+
+// How many unbalanced brackets get marked, and how deep
+// brackets can nest before we stop keeping track of which is which
+const MAX_UNMATCHED: usize = 16;
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Plain,
+    Keyword,
+    Literal,
+    Str,
+    Operator,
+    Comment,
+    Open,
+    Close,
+}
+
+#[derive(Clone, Copy)]
+pub struct Token {
+    pub kind: Kind,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Lexer<'a> {
+    doc: &'a GapBuffer,
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(doc: &'a GapBuffer, start: usize, end: usize) -> Self {
+        Self {
+            doc,
+            pos: start,
+            end,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        if self.pos < self.end {
+            self.doc.get(self.pos)
+        } else {
+            None
+        }
+    }
+
+    fn skip_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
+
+    fn is_one_of(&self, start: usize, words: &[&[u8]]) -> bool {
+        words
+            .iter()
+            .any(|w| w.len() == self.pos - start && self.doc.matches_at(start, w))
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let start = self.pos;
+        let b = self.peek()?;
+        self.pos += 1;
+        let kind = match b {
+            b'#' => {
+                self.skip_while(|b| b != b'\n');
+                Kind::Comment
+            }
+            b'"' => {
+                self.skip_while(|b| b != b'"' && b != b'\n');
+                if self.peek() == Some(b'"') {
+                    self.pos += 1;
+                }
+                Kind::Str
+            }
+            b'0'..=b'9' => {
+                self.skip_while(|b| b.is_ascii_digit() || b == b'.');
+                Kind::Literal
+            }
+            b'(' | b'{' | b'[' => Kind::Open,
+            b')' | b'}' | b']' => Kind::Close,
+            b if is_word(b) => {
+                self.skip_while(|b| is_word(b) || b.is_ascii_digit());
+                if self.is_one_of(start, &KEYWORDS) {
+                    Kind::Keyword
+                } else if self.is_one_of(start, &LITERALS) {
+                    Kind::Literal
+                } else {
+                    Kind::Plain
+                }
+            }
+            b if is_operator(b) => {
+                self.skip_while(is_operator);
+                Kind::Operator
+            }
+            _ => Kind::Plain,
+        };
+        Some(Token {
+            kind,
+            start,
+            end: self.pos,
+        })
+    }
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+fn is_operator(b: u8) -> bool {
+    b":=+-*/%<>!&|".contains(&b)
+}
+
+fn closer(open: u8) -> u8 {
+    match open {
+        b'(' => b')',
+        b'{' => b'}',
+        _ => b']',
+    }
+}
+
+pub fn color(kind: Kind) -> ColorCode {
+    let fg = match kind {
+        Kind::Plain | Kind::Open | Kind::Close => Color::LightGray,
+        Kind::Keyword => Color::LightCyan,
+        Kind::Literal => Color::Pink,
+        Kind::Str => Color::LightGreen,
+        Kind::Operator => Color::White,
+        Kind::Comment => Color::DarkGray,
+    };
+    ColorCode::new(fg, Color::Black)
+}
+
+pub fn unmatched_color() -> ColorCode {
+    ColorCode::new(Color::White, Color::Red)
+}
+
+pub fn matched_color() -> ColorCode {
+    ColorCode::new(Color::Black, Color::Cyan)
+}
+
+enum Pairing {
+    Matched(usize, usize),
+    Unmatched(usize),
+}
+
+// Walks the whole document pairing each closing bracket with the last
+// unclosed opening one, telling f about every pair and every bracket
+// left over. Stops early once f returns false.
+fn pair_brackets(doc: &GapBuffer, mut f: impl FnMut(Pairing) -> bool) {
+    let mut open = [(0, 0); MAX_DEPTH];
+    let mut depth = 0;
+    for token in Lexer::new(doc, 0, doc.len()) {
+        let b = doc.get(token.start).unwrap_or(0);
+        let keep_going = match token.kind {
+            Kind::Open => {
+                if depth < MAX_DEPTH {
+                    open[depth] = (token.start, b);
+                }
+                depth += 1;
+                true
+            }
+            // past MAX_DEPTH we have no idea which kind of bracket is open
+            Kind::Close if depth > MAX_DEPTH => {
+                depth -= 1;
+                true
+            }
+            Kind::Close if depth > 0 && closer(open[depth - 1].1) == b => {
+                depth -= 1;
+                f(Pairing::Matched(open[depth].0, token.start))
+            }
+            Kind::Close => f(Pairing::Unmatched(token.start)),
+            _ => true,
+        };
+        if !keep_going {
+            return;
+        }
+    }
+    for (at, _) in open.iter().take(depth) {
+        if !f(Pairing::Unmatched(*at)) {
+            return;
+        }
+    }
+}
+
+// Whether a bracket is balanced depends on the whole document, not just
+// the line it is on. So the unbalanced ones are found in one pass and
+// remembered until the document changes, instead of every frame.
+// The same goes for the bracket paired with the one under the cursor.
+#[derive(Default)]
+pub struct Brackets {
+    version: Option<usize>,
+    unmatched: [usize; MAX_UNMATCHED],
+    count: usize,
+    // (version, offset) the pair below was found for
+    matched_for: Option<(usize, usize)>,
+    matched: Option<usize>,
+}
+
+impl Brackets {
+    pub fn refresh(&mut self, doc: &GapBuffer) {
+        if self.version == Some(doc.version()) {
+            return;
+        }
+        self.version = Some(doc.version());
+        self.count = 0;
+        pair_brackets(doc, |pairing| {
+            if let Pairing::Unmatched(at) = pairing {
+                self.mark(at);
+            }
+            true
+        });
+    }
+
+    fn mark(&mut self, at: usize) {
+        if self.count < MAX_UNMATCHED {
+            self.unmatched[self.count] = at;
+            self.count += 1;
+        }
+    }
+
+    pub fn is_unmatched(&self, at: usize) -> bool {
+        self.unmatched[..self.count].contains(&at)
+    }
+
+    // Finds the bracket paired with the one at offset at, if there is one there
+    pub fn find_match(&mut self, doc: &GapBuffer, at: usize) {
+        if self.matched_for == Some((doc.version(), at)) {
+            return;
+        }
+        self.matched_for = Some((doc.version(), at));
+        self.matched = None;
+        if !matches!(doc.get(at), Some(b'(' | b')' | b'{' | b'}' | b'[' | b']')) {
+            return;
+        }
+        pair_brackets(doc, |pairing| match pairing {
+            Pairing::Matched(open, close) if open == at => {
+                self.matched = Some(close);
+                false
+            }
+            Pairing::Matched(open, close) if close == at => {
+                self.matched = Some(open);
+                false
+            }
+            _ => true,
+        });
+    }
+
+    pub fn matched(&self) -> Option<usize> {
+        self.matched
+    }
+}
@@ -0,0 +1,508 @@
+use core::fmt::{self, Write};
+use simple_interp::ArrayString;
+
+use super::{Mode, TextEditor};
+use crate::{
+    app::{clipboard::Clipboard, prompt::Prompt},
+    FsType,
+};
+
+// An opt-in layer of vi keys on top of the editor, turned on and off per
+// window with F10. Normal and visual mode take over the printable keys,
+// insert mode types like the plain editor does. Everything here moves the
+// cursor and edits through the same calls the plain editor uses, so undo,
+// the clipboard and the highlighting all keep working.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    Insert,
+    Visual,
+}
+
+// What a key turned out to be, to know whether . should repeat it
+enum Done {
+    // the first part of something longer, like a count or the d of dw
+    Pending,
+    Motion,
+    Change,
+    // a change that carries on with whatever gets typed until Esc
+    Insert,
+}
+
+pub struct Vi {
+    state: State,
+    // 0 until a count is typed
+    count: usize,
+    // an operator waiting for its motion, with the count typed before it
+    op: Option<(char, usize)>,
+    // saw the first g of gg
+    g: bool,
+    // the : command being typed in the title bar
+    command: Option<Prompt<16>>,
+    // Keys of the change being typed and of the last finished one, for .
+    // A change with more keys than fit is only partly repeated.
+    keys: Prompt<128>,
+    last_change: Prompt<128>,
+    replaying: bool,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self {
+            state: State::Normal,
+            count: 0,
+            op: None,
+            g: false,
+            command: None,
+            keys: Prompt::default(),
+            last_change: Prompt::default(),
+            replaying: false,
+        }
+    }
+}
+
+impl Vi {
+    pub fn write_title(&self, a: &mut ArrayString<64>, name: &str, modified: bool) -> fmt::Result {
+        if let Some(command) = &self.command {
+            return write!(a, ":{}", command.as_str());
+        }
+        write!(
+            a,
+            "VI:{}{} -- {} --",
+            name,
+            if modified { "*" } else { "" },
+            match self.state {
+                State::Normal => "NORMAL",
+                State::Insert => "INSERT",
+                State::Visual => "VISUAL",
+            }
+        )
+    }
+}
+
+impl TextEditor {
+    pub fn toggle_vi(&mut self) {
+        self.vi = match self.vi {
+            Some(_) => None,
+            None => Some(Vi::default()),
+        };
+        self.anchor = None;
+
+        let mut status = ArrayString::<64>::default();
+        let _ = write!(
+            status,
+            "vi keys {}",
+            if self.vi.is_some() {
+                "on, F10 for plain typing"
+            } else {
+                "off"
+            }
+        );
+        self.status = Some(status);
+    }
+
+    // true if printable keys are vi commands right now rather than text
+    pub(super) fn vi_commands(&self) -> bool {
+        matches!(self.mode, Mode::Edit)
+            && self.vi.as_ref().is_some_and(|vi| vi.state != State::Insert)
+    }
+
+    // Typing in insert mode is remembered as part of the change for .
+    pub(super) fn vi_record(&mut self, c: char) {
+        if let Some(vi) = &mut self.vi {
+            if vi.state == State::Insert && !vi.replaying {
+                vi.keys.push(c);
+            }
+        }
+    }
+
+    // A key in normal or visual mode, true if it was :q or :wq
+    pub(super) fn vi_key(&mut self, c: char, fs: &mut FsType, clipboard: &mut Clipboard) -> bool {
+        if let Some(vi) = &mut self.vi {
+            if c == '.' && vi.state == State::Normal && vi.op.is_none() && vi.command.is_none() {
+                vi.count = 0;
+                vi.g = false;
+                self.vi_repeat(fs, clipboard);
+                return false;
+            }
+        }
+
+        // The Vi is taken out while we work so the editor can be changed freely
+        let Some(mut vi) = self.vi.take() else {
+            return false;
+        };
+        if let Some(command) = &mut vi.command {
+            command.push(c);
+        } else {
+            let recording = vi.state == State::Normal && !vi.replaying;
+            if recording && vi.count == 0 && vi.op.is_none() && !vi.g {
+                vi.keys.clear();
+            }
+            if recording {
+                vi.keys.push(c);
+            }
+            if let Done::Change = self.vi_command(&mut vi, c, clipboard) {
+                if recording {
+                    vi.last_change = vi.keys;
+                }
+            }
+        }
+        self.vi = Some(vi);
+        false
+    }
+
+    fn vi_command(&mut self, vi: &mut Vi, c: char, clipboard: &mut Clipboard) -> Done {
+        if c.is_ascii_digit() && (c != '0' || vi.count > 0) {
+            vi.count = vi
+                .count
+                .saturating_mul(10)
+                .saturating_add(c as usize - '0' as usize);
+            return Done::Pending;
+        }
+        let typed = vi.count;
+        vi.count = 0;
+        let count = usize::max(typed, 1);
+
+        if vi.g {
+            vi.g = false;
+            if c == 'g' {
+                return self.vi_motion(vi, 'g', typed, clipboard);
+            }
+            vi.op = None;
+            return Done::Motion;
+        }
+
+        let visual = vi.state == State::Visual;
+        let (col, end) = (
+            self.doc.line_col(self.cursor).1,
+            self.doc.line_end(self.cursor),
+        );
+        match c {
+            'g' => {
+                vi.g = true;
+                vi.count = typed;
+                Done::Pending
+            }
+            'h' | 'j' | 'k' | 'l' | 'w' | 'b' | 'e' | '0' | '$' | 'G' => {
+                self.vi_motion(vi, c, typed, clipboard)
+            }
+            'd' | 'c' | 'y' if visual => self.vi_visual_op(vi, c, clipboard),
+            'x' if visual => self.vi_visual_op(vi, 'd', clipboard),
+            'd' | 'c' | 'y' => match vi.op.take() {
+                // doubled, like dd, works on whole lines
+                Some((op, before)) if op == c => {
+                    let (line, _) = self.doc.line_col(self.cursor);
+                    let last = self.doc.line_count() - 1;
+                    let to = usize::min(line + before * count - 1, last);
+                    let to = self.doc.line_start(to).unwrap_or(self.cursor);
+                    self.vi_lines(vi, op, self.cursor, to, clipboard)
+                }
+                Some(_) => Done::Motion,
+                None => {
+                    vi.op = Some((c, count));
+                    Done::Pending
+                }
+            },
+            'x' => {
+                let to = usize::min(self.cursor + count, end);
+                self.vi_operate(vi, 'd', self.cursor, to, clipboard)
+            }
+            'p' | 'P' => {
+                if c == 'p' && self.cursor < end {
+                    self.cursor += 1;
+                }
+                for _ in 0..count {
+                    self.paste(clipboard);
+                }
+                Done::Change
+            }
+            'i' | 'a' | 'I' | 'A' | 'o' | 'O' if !visual => {
+                self.history.seal();
+                match c {
+                    'a' if self.cursor < end => self.cursor += 1,
+                    'I' => self.cursor -= col,
+                    'A' => self.cursor = end,
+                    'o' => {
+                        self.cursor = end;
+                        self.auto_newline();
+                    }
+                    'O' => {
+                        self.cursor -= col;
+                        self.edit_insert(b'\n');
+                        self.cursor -= 1;
+                    }
+                    _ => {}
+                }
+                vi.state = State::Insert;
+                Done::Insert
+            }
+            'v' if visual => {
+                vi.state = State::Normal;
+                self.anchor = None;
+                Done::Motion
+            }
+            'v' => {
+                vi.state = State::Visual;
+                self.anchor = Some(self.cursor);
+                Done::Motion
+            }
+            'u' => {
+                for _ in 0..count {
+                    self.undo();
+                }
+                Done::Motion
+            }
+            ':' => {
+                vi.command = Some(Prompt::default());
+                Done::Motion
+            }
+            _ => {
+                vi.op = None;
+                Done::Motion
+            }
+        }
+    }
+
+    // Moves the cursor, or if an operator is waiting, applies it to
+    // everything between where the cursor was and where it would go
+    fn vi_motion(&mut self, vi: &mut Vi, c: char, typed: usize, clipboard: &mut Clipboard) -> Done {
+        let count = usize::max(typed, 1) * vi.op.map_or(1, |(_, before)| before);
+        // moving in visual mode drags the selection along
+        let visual = vi.state == State::Visual;
+        let from = self.cursor;
+        self.history.seal();
+        match c {
+            // G and gg take the count as a line number instead
+            'G' | 'g' => {
+                let last = self.doc.line_count() - 1;
+                let line = match (c, typed) {
+                    ('G', 0) => last,
+                    (_, 0) => 0,
+                    (_, n) => usize::min(n - 1, last),
+                };
+                self.cursor = self.doc.line_start(line).unwrap_or(0);
+            }
+            '0' => {
+                let (_, col) = self.doc.line_col(self.cursor);
+                self.cursor -= col;
+            }
+            '$' => self.cursor = self.doc.line_end(self.cursor),
+            _ => {
+                for _ in 0..count {
+                    match c {
+                        'h' if self.doc.line_col(self.cursor).1 > 0 => self.cursor -= 1,
+                        'l' if self.cursor < self.doc.line_end(self.cursor) => self.cursor += 1,
+                        'j' => self.arrow_down(visual),
+                        'k' => self.arrow_up(visual),
+                        'w' => self.word_right(visual),
+                        'b' => self.word_left(visual),
+                        'e' => self.word_end(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let Some((op, _)) = vi.op.take() else {
+            return Done::Motion;
+        };
+        let to = self.cursor;
+        self.cursor = from;
+        if matches!(c, 'j' | 'k' | 'G' | 'g') {
+            self.vi_lines(vi, op, from, to, clipboard)
+        } else {
+            let start = usize::min(from, to);
+            let mut end = usize::max(from, to);
+            // e takes the last letter of the word along with it
+            if c == 'e' {
+                end = usize::min(end + 1, self.doc.len());
+            }
+            self.vi_operate(vi, op, start, end, clipboard)
+        }
+    }
+
+    fn word_end(&mut self) {
+        let len = self.doc.len();
+        if self.cursor < len {
+            self.cursor += 1;
+        }
+        while self.cursor < len && !self.is_word(self.cursor) {
+            self.cursor += 1;
+        }
+        while self.cursor + 1 < len && self.is_word(self.cursor + 1) {
+            self.cursor += 1;
+        }
+    }
+
+    // An operator over every line from the one with a to the one with b
+    fn vi_lines(
+        &mut self,
+        vi: &mut Vi,
+        op: char,
+        a: usize,
+        b: usize,
+        clipboard: &mut Clipboard,
+    ) -> Done {
+        let start = usize::min(a, b);
+        let mut start = start - self.doc.line_col(start).1;
+        let mut end = self.doc.line_end(usize::max(a, b));
+        // c leaves an empty line to type on, d and y take the line break too
+        if op != 'c' {
+            if end < self.doc.len() {
+                end += 1;
+            } else if op == 'd' && start > 0 {
+                start -= 1;
+            }
+        }
+        self.vi_operate(vi, op, start, end, clipboard)
+    }
+
+    fn vi_visual_op(&mut self, vi: &mut Vi, op: char, clipboard: &mut Clipboard) -> Done {
+        // unlike a Shift+arrow selection, visual mode includes the character under the cursor
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        let start = usize::min(anchor, self.cursor);
+        let end = usize::min(usize::max(anchor, self.cursor) + 1, self.doc.len());
+        vi.state = State::Normal;
+        self.vi_operate(vi, op, start, end, clipboard)
+    }
+
+    // d and c go through cut and y through copy, so they fill the same clipboard
+    fn vi_operate(
+        &mut self,
+        vi: &mut Vi,
+        op: char,
+        start: usize,
+        end: usize,
+        clipboard: &mut Clipboard,
+    ) -> Done {
+        self.history.seal();
+        self.anchor = Some(start);
+        self.cursor = end;
+        let done = match op {
+            'y' => {
+                self.copy(clipboard);
+                self.cursor = start;
+                Done::Motion
+            }
+            'd' => {
+                self.cut(clipboard);
+                Done::Change
+            }
+            _ => {
+                self.cut(clipboard);
+                vi.state = State::Insert;
+                Done::Insert
+            }
+        };
+        self.anchor = None;
+        done
+    }
+
+    // . replays the keys of the last change as if they were typed again
+    fn vi_repeat(&mut self, fs: &mut FsType, clipboard: &mut Clipboard) {
+        let Some(vi) = &mut self.vi else {
+            return;
+        };
+        let keys = vi.last_change;
+        vi.replaying = true;
+        for c in keys.as_bytes() {
+            match *c {
+                b'\x1B' => self.escape(),
+                b'\n' => {
+                    self.newline(fs);
+                }
+                b'\x08' => self.backspace(),
+                c => {
+                    self.insert_char(c as char, fs, clipboard);
+                }
+            }
+        }
+        self.vi_escape();
+        if let Some(vi) = &mut self.vi {
+            vi.replaying = false;
+        }
+    }
+
+    // Esc out of insert or visual mode, or out of a half typed command.
+    // false if there was nothing for vi to do with it.
+    pub(super) fn vi_escape(&mut self) -> bool {
+        let Some(vi) = &mut self.vi else {
+            return false;
+        };
+        vi.count = 0;
+        vi.op = None;
+        vi.g = false;
+        let had_command = vi.command.take().is_some();
+        match vi.state {
+            State::Insert => {
+                vi.state = State::Normal;
+                if !vi.replaying {
+                    vi.keys.push('\x1B');
+                    vi.last_change = vi.keys;
+                }
+                self.history.seal();
+                true
+            }
+            State::Visual => {
+                vi.state = State::Normal;
+                self.anchor = None;
+                true
+            }
+            State::Normal => had_command,
+        }
+    }
+
+    // Enter runs a : command, and otherwise moves down a line like j.
+    // true if the command was to close the editor.
+    pub(super) fn vi_newline(&mut self, fs: &mut FsType) -> bool {
+        let Some(vi) = &mut self.vi else {
+            return false;
+        };
+        let Some(command) = vi.command.take() else {
+            let visual = vi.state == State::Visual;
+            self.arrow_down(visual);
+            return false;
+        };
+
+        let mut status = ArrayString::<64>::default();
+        let close = match command.as_str() {
+            "w" => {
+                self.save(fs);
+                false
+            }
+            "wq" | "x" => self.save(fs),
+            "q" if self.is_modified() => {
+                let _ = write!(status, "Unsaved changes, :q! to throw them away");
+                false
+            }
+            "q" | "q!" => true,
+            other => {
+                let _ = write!(status, "Not a command: {other}");
+                false
+            }
+        };
+        if !status.as_str().unwrap_or("").is_empty() {
+            self.status = Some(status);
+        }
+        close
+    }
+
+    // Backspace takes back a letter of a : command, and otherwise moves left like h
+    pub(super) fn vi_backspace(&mut self) {
+        let Some(vi) = &mut self.vi else {
+            return;
+        };
+        match &mut vi.command {
+            Some(command) => {
+                if command.pop().is_none() {
+                    vi.command = None;
+                }
+            }
+            None => {
+                if self.doc.line_col(self.cursor).1 > 0 {
+                    self.cursor -= 1;
+                }
+            }
+        }
+    }
+}
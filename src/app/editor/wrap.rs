@@ -0,0 +1,106 @@
+use core::fmt::Write;
+use simple_interp::ArrayString;
+
+use super::TextEditor;
+
+// How a line longer than the window is laid out. Soft and Word both
+// spread it over more rows, None keeps every line on one row and
+// scrolls sideways to follow the cursor instead.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Soft,
+    Word,
+    None,
+}
+
+impl TextEditor {
+    // F9 goes Soft -> Word -> None -> Soft
+    pub fn cycle_wrap(&mut self) {
+        self.wrap = match self.wrap {
+            Wrap::Soft => Wrap::Word,
+            Wrap::Word => Wrap::None,
+            Wrap::None => Wrap::Soft,
+        };
+        self.hscroll = 0;
+
+        let mut status = ArrayString::<64>::default();
+        let _ = write!(
+            status,
+            "Wrap: {}",
+            match self.wrap {
+                Wrap::Soft => "soft",
+                Wrap::Word => "at words",
+                Wrap::None => "off, scrolling sideways",
+            }
+        );
+        self.status = Some(status);
+    }
+
+    // Offset where the screen row after the one starting at row_start
+    // begins, or None if that was the last row of its line. A line that
+    // exactly fills its last row still gets another for the cursor to sit on.
+    pub(super) fn next_row(&self, row_start: usize) -> Option<usize> {
+        let width = self.text.width();
+        let end = self.doc.line_end(row_start);
+        if self.wrap == Wrap::None || end - row_start < width {
+            return None;
+        }
+        let limit = row_start + width;
+        if self.wrap == Wrap::Word {
+            // break after the last space that fits, or mid-word if there isn't one
+            if let Some(space) = (row_start + 1..limit)
+                .rev()
+                .find(|i| self.doc.get(*i) == Some(b' '))
+            {
+                return Some(space + 1);
+            }
+        }
+        Some(limit)
+    }
+
+    pub(super) fn rows_in_line(&self, start: usize) -> usize {
+        let mut rows = 1;
+        let mut row = start;
+        while let Some(next) = self.next_row(row) {
+            rows += 1;
+            row = next;
+        }
+        rows
+    }
+
+    // (offset the row pos is drawn on starts at, which row of its line that is)
+    pub(super) fn row_of(&self, pos: usize) -> (usize, usize) {
+        let (_, col) = self.doc.line_col(pos);
+        let mut row = pos - col;
+        let mut index = 0;
+        while let Some(next) = self.next_row(row).filter(|next| *next <= pos) {
+            row = next;
+            index += 1;
+        }
+        (row, index)
+    }
+
+    // The last offset the cursor can sit at on the row starting at row_start
+    pub(super) fn row_last(&self, row_start: usize) -> usize {
+        match self.next_row(row_start) {
+            Some(next) => next - 1,
+            None => self.doc.line_end(row_start),
+        }
+    }
+
+    // Scrolling sideways only happens without wrapping, just
+    // far enough to keep the cursor's column in the window
+    pub(super) fn follow_cursor_sideways(&mut self) {
+        if self.wrap != Wrap::None {
+            self.hscroll = 0;
+            return;
+        }
+        let width = self.text.width();
+        let (_, col) = self.doc.line_col(self.cursor);
+        if col < self.hscroll {
+            self.hscroll = col;
+        } else if col >= self.hscroll + width {
+            self.hscroll = col + 1 - width;
+        }
+    }
+}
@@ -1,27 +1,231 @@
-use file_system_solution::FileSystemError;
-use pluggable_interrupt_os::vga_buffer::{Color, ColorCode};
+use core::fmt::{Display, Write};
 use simple_interp::ArrayString;
 
-use crate::{FsType, MAX_FILENAME_BYTES};
+use crate::{
+    fs::{self, DirEntry, FileSystemError},
+    scratch::with_file_buffer,
+    surface::Surface,
+    vga::{Color, ColorCode},
+    FsType, BLOCK_SIZE, MAX_FILENAME_BYTES, MAX_FILES_STORED, MAX_FILE_BLOCKS, MAX_PATH_BYTES,
+};
 
-use super::window::Window;
+use super::{prompt::Prompt, window::Window};
 
+fn plain() -> ColorCode {
+    ColorCode::new(Color::LightGray, Color::Black)
+}
+
+fn highlight() -> ColorCode {
+    ColorCode::new(Color::Black, Color::LightGray)
+}
+
+// The file operations that need a name typed into the title bar first
+#[derive(Clone, Copy)]
+enum Action {
+    New,
+    Copy,
+    Rename,
+}
+
+// Shows one directory at a time. Enter goes down into the one selected
+// and Backspace back out, and + with a name ending in '/' makes one.
+// Names starting with '.' aren't shown at all.
 pub struct Explorer {
     selected: usize,
+    // what's in dir, each under its own name
     num_files: usize,
-    names: [[u8; MAX_FILENAME_BYTES]; 3 * 10], // 3 cols, 10 rows
+    entries: [DirEntry; MAX_FILES_STORED],
+    // the path of the directory being shown, ending in '/' unless it's the top
+    dir: Prompt<MAX_PATH_BYTES>,
+    // Some while +, c or n is waiting for a name
+    prompt: Option<(Action, Prompt<MAX_PATH_BYTES>)>,
+    // d waiting on y or n
+    deleting: bool,
+    // a message for the status bar, picked up by SwimInterface
+    status: Option<ArrayString<64>>,
+    // set after making a file, so every Explorer can catch up
+    files_changed: bool,
+    // i, one file a row with its size instead of just names
+    details: bool,
+    // bytes in each file, and what's left on the whole disk,
+    // only kept up to date while showing details
+    sizes: [usize; MAX_FILES_STORED],
+    free: (usize, usize),
     pub window: Window,
 }
 
 impl Explorer {
     pub fn new(window: Window, fs: &mut FsType) -> Self {
-        let (num_files, names) = fs.list_directory().unwrap();
-        Explorer {
+        Self::showing(window, "", fs)
+    }
+
+    // Opens in the directory path is in, with path selected. Back
+    // at the top if that directory isn't there any more.
+    pub fn showing(window: Window, path: &str, fs: &mut FsType) -> Self {
+        let mut explorer = Explorer {
             selected: 0,
-            num_files,
-            names,
+            num_files: 0,
+            entries: [DirEntry::EMPTY; MAX_FILES_STORED],
+            dir: Prompt::default(),
+            prompt: None,
+            deleting: false,
+            status: None,
+            files_changed: false,
+            details: false,
+            sizes: [0; MAX_FILES_STORED],
+            free: (0, 0),
             window,
+        };
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if !dir.is_empty() {
+            for c in dir.chars().chain(Some('/')) {
+                explorer.dir.push(c);
+            }
+        }
+        explorer.refresh(fs);
+        explorer.select(name.as_bytes());
+        explorer
+    }
+
+    // Reads the directory again, keeping the selection where it can. If
+    // it's been moved or deleted from somewhere else it's back to the top.
+    pub fn refresh(&mut self, fs: &mut FsType) {
+        match self.list(fs) {
+            Ok(()) => self.selected = usize::min(self.selected, self.last()),
+            Err(_) if !self.dir.is_empty() => {
+                self.dir.clear();
+                self.selected = 0;
+                self.refresh(fs);
+            }
+            Err(e) => self.error(e),
+        }
+    }
+
+    fn select(&mut self, name: &[u8]) {
+        self.selected = (0..self.num_files)
+            .find(|i| self.shown(*i) == name)
+            .unwrap_or(0);
+    }
+
+    pub fn error(&mut self, e: impl Display) {
+        let mut status = ArrayString::<64>::default();
+        let _ = write!(status, "ERROR {e}");
+        self.status = Some(status);
+    }
+
+    fn list(&mut self, fs: &mut FsType) -> Result<(), FileSystemError> {
+        let (count, entries) = fs.list_directory(self.dir.as_str())?;
+        self.num_files = 0;
+        for entry in entries[..count]
+            .iter()
+            .filter(|entry| entry.name[0] != b'.')
+        {
+            self.entries[self.num_files] = *entry;
+            self.num_files += 1;
+        }
+        if self.details {
+            self.read_sizes(fs);
+        }
+        Ok(())
+    }
+
+    fn is_dir(&self, i: usize) -> bool {
+        self.entries[i].is_dir
+    }
+
+    pub fn is_dir_selected(&self) -> bool {
+        self.num_files > 0 && self.is_dir(self.selected)
+    }
+
+    // Enter on a directory goes into it, as long as the
+    // paths of what's in it would still fit in MAX_PATH_BYTES
+    pub fn enter(&mut self, fs: &mut FsType) {
+        if !self.is_dir_selected() {
+            return;
+        }
+        let name = self.entries[self.selected].name;
+        let name = trimmed(&name);
+        if self.dir.len() + name.len() + 1 + MAX_FILENAME_BYTES > MAX_PATH_BYTES {
+            return self.error("path too long");
+        }
+        for b in name {
+            self.dir.push(*b as char);
+        }
+        self.dir.push('/');
+        self.selected = 0;
+        self.refresh(fs);
+    }
+
+    // Backspace goes back up to the directory this one is in,
+    // with this one selected
+    pub fn up(&mut self, fs: &mut FsType) {
+        let came_from = self.dir;
+        if came_from.is_empty() {
+            return;
+        }
+        self.dir.pop();
+        while !self.dir.is_empty() && !self.dir.as_str().ends_with('/') {
+            self.dir.pop();
         }
+        self.refresh(fs);
+        self.select(&came_from.as_bytes()[self.dir.len()..came_from.len() - 1]);
+    }
+
+    // What gets drawn for entry i, its name without the directory it's in
+    fn shown(&self, i: usize) -> &[u8] {
+        trimmed(&self.entries[i].name)
+    }
+
+    // Entry i's path from the top
+    fn path(&self, i: usize) -> ArrayString<MAX_PATH_BYTES> {
+        let mut a = ArrayString::<MAX_PATH_BYTES>::default();
+        for b in self.dir.as_bytes().iter().chain(self.shown(i)) {
+            a.push_char(*b as char);
+        }
+        a
+    }
+
+    pub fn toggle_details(&mut self, fs: &mut FsType) {
+        self.details = !self.details;
+        if self.details {
+            self.read_sizes(fs);
+        }
+    }
+
+    fn read_sizes(&mut self, fs: &mut FsType) {
+        for i in 0..self.num_files {
+            self.sizes[i] = match self.path(i).as_str() {
+                Ok(path) if !self.is_dir(i) => fs.file_size(path).unwrap_or(0),
+                _ => 0,
+            };
+        }
+        let free_blocks = fs.free_blocks().unwrap_or(0);
+        self.free = (free_blocks, fs.free_inodes().unwrap_or(0));
+    }
+
+    pub fn title(&self) -> ArrayString<64> {
+        let mut a = ArrayString::<64>::default();
+        let _ = match &self.prompt {
+            Some((Action::New, name)) => write!(a, "NEW FILE:{}", name.as_str()),
+            Some((Action::Copy, name)) => write!(a, "COPY TO:{}", name.as_str()),
+            Some((Action::Rename, name)) => write!(a, "RENAME TO:{}", name.as_str()),
+            None if self.deleting => write!(
+                a,
+                "DELETE {}? (y/n)",
+                str::from_utf8(self.shown(self.selected)).unwrap_or("?")
+            ),
+            None if self.dir.is_empty() => write!(a, "(e)dit,(r)un,(c)opy,(+)new"),
+            None => write!(a, "/{}:(e)dit,(r)un,(+)new", self.dir.as_str()),
+        };
+        a
+    }
+
+    pub fn take_status(&mut self) -> Option<ArrayString<64>> {
+        self.status.take()
+    }
+
+    pub fn take_files_changed(&mut self) -> bool {
+        core::mem::take(&mut self.files_changed)
     }
 
     pub fn read_selected(
@@ -29,84 +233,340 @@ impl Explorer {
         buf: &mut [u8],
         fs: &mut FsType,
     ) -> Result<usize, FileSystemError> {
-        let filename = self.name_as_slice(self.selected);
-        let filename = str::from_utf8(filename).map_err(|_| FileSystemError::FileNotFound)?;
-        let fd = fs.open_read(filename)?;
-        let n = fs.read(fd, buf)?;
+        self.read_at(self.selected, buf, fs)
+    }
+
+    fn read_at(&self, i: usize, buf: &mut [u8], fs: &mut FsType) -> Result<usize, FileSystemError> {
+        let path = self.path(i);
+        let path = path.as_str().map_err(|_| FileSystemError::FileNotFound)?;
+        let fd = fs.open_read(path)?;
+        // close it even if the read fails, or it's open for good
+        let n = fs.read(fd, buf);
         fs.close(fd)?;
-        Ok(n)
+        n
     }
 
-    pub fn name(&self) -> ArrayString<MAX_FILENAME_BYTES> {
-        let mut a: ArrayString<MAX_FILENAME_BYTES> = Default::default();
+    // The selected entry's path from the top
+    pub fn name(&self) -> ArrayString<MAX_PATH_BYTES> {
+        self.path(self.selected)
+    }
 
-        for c in self.name_as_slice(self.selected) {
-            a.push_char(*c as char);
+    // (columns, rows) of names that fit in the window. If they don't
+    // all fit at once the bottom row is kept for the page indicator.
+    // Details always keep it, for the free space footer.
+    fn grid(&self) -> (usize, usize) {
+        let cols = usize::max(self.window.width() / MAX_FILENAME_BYTES, 1);
+        let rows = self.window.height();
+        if self.details {
+            (1, usize::max(rows - 1, 1))
+        } else if cols * rows < self.num_files {
+            (cols, usize::max(rows - 1, 1))
+        } else {
+            (cols, rows)
         }
+    }
 
-        a
+    fn per_page(&self) -> usize {
+        let (cols, rows) = self.grid();
+        cols * rows
+    }
+
+    fn page(&self) -> usize {
+        self.selected / self.per_page()
+    }
+
+    fn pages(&self) -> usize {
+        usize::max(self.num_files.div_ceil(self.per_page()), 1)
+    }
+
+    fn last(&self) -> usize {
+        self.num_files.saturating_sub(1)
     }
 
-    fn name_as_slice(&self, i: usize) -> &[u8] {
-        let mut end = MAX_FILENAME_BYTES;
-        for j in 0..MAX_FILENAME_BYTES {
-            if self.names[i][j] == 0 {
-                end = j;
-                break;
+    pub fn draw(&self, surface: &mut impl Surface) {
+        if self.details {
+            self.draw_details(surface);
+        } else {
+            self.draw_names(surface);
+        }
+
+        if self.pages() > 1 {
+            let mut indicator = ArrayString::<16>::default();
+            let _ = write!(indicator, "page {}/{}", self.page() + 1, self.pages());
+            let indicator = indicator.as_str().unwrap_or("");
+            let x = self.window.width().saturating_sub(indicator.len());
+            for (i, c) in indicator.chars().enumerate().take(self.window.width()) {
+                let y = self.window.height() - 1;
+                self.window
+                    .plot(surface, c, (x + i) as u8, y as u8, plain());
             }
         }
-        &self.names[i][0..end]
-    }
-
-    pub fn draw(&self) {
-        for row in 0..10 {
-            for col in 0..3 {
-                for ci in 0..MAX_FILENAME_BYTES {
-                    let idx = row * 3 + col;
-                    let color = if idx == self.selected {
-                        ColorCode::new(Color::Black, Color::LightGray)
-                    } else {
-                        ColorCode::new(Color::LightGray, Color::Black)
-                    };
-
-                    self.window.plot(
-                        self.names[idx][ci] as char,
-                        (col * MAX_FILENAME_BYTES + ci) as u8,
-                        row as u8,
-                        color,
-                    );
-                }
+    }
+
+    fn draw_details(&self, surface: &mut impl Surface) {
+        let (_, rows) = self.grid();
+        let first = self.page() * rows;
+        for row in 0..rows {
+            let idx = first + row;
+            let mut line = ArrayString::<64>::default();
+            if idx < self.num_files {
+                let name = str::from_utf8(self.shown(idx)).unwrap_or("?");
+                let size = self.sizes[idx];
+                let _ = if self.is_dir(idx) {
+                    write!(line, "{name:<MAX_FILENAME_BYTES$} <dir>")
+                } else {
+                    write!(
+                        line,
+                        "{name:<MAX_FILENAME_BYTES$} {size:>5}B {:>2}/{MAX_FILE_BLOCKS} blocks",
+                        size.div_ceil(BLOCK_SIZE)
+                    )
+                };
+            }
+            let color = if idx == self.selected {
+                highlight()
+            } else {
+                plain()
+            };
+            self.draw_line(surface, row, line.as_str().unwrap_or(""), color);
+        }
+
+        // for the whole disk, not just this directory
+        let mut footer = ArrayString::<64>::default();
+        let _ = write!(footer, "free:{} blocks,{} files", self.free.0, self.free.1);
+        let y = self.window.height() - 1;
+        self.draw_line(surface, y, footer.as_str().unwrap_or(""), plain());
+    }
+
+    // text on one row of the window, with the rest of the row blanked
+    fn draw_line(&self, surface: &mut impl Surface, row: usize, text: &str, color: ColorCode) {
+        let mut chars = text.chars();
+        for x in 0..self.window.width() {
+            let c = chars.next().unwrap_or(' ');
+            self.window.plot(surface, c, x as u8, row as u8, color);
+        }
+    }
+
+    fn draw_names(&self, surface: &mut impl Surface) {
+        let (cols, rows) = self.grid();
+        let first = self.page() * cols * rows;
+
+        // Every cell gets drawn, so whatever was there before goes away
+        for row in 0..self.window.height() {
+            for x in 0..self.window.width() {
+                let (col, ci) = (x / MAX_FILENAME_BYTES, x % MAX_FILENAME_BYTES);
+                let idx = first + row * cols + col;
+                let shown = row < rows && col < cols && idx < self.num_files;
+                // a '/' after a directory, unless its name fills the cell
+                let c = if shown {
+                    let name = self.shown(idx);
+                    match name.get(ci) {
+                        Some(b) => *b as char,
+                        None if ci == name.len() && self.is_dir(idx) => '/',
+                        None => ' ',
+                    }
+                } else {
+                    ' '
+                };
+                let color = if shown && idx == self.selected {
+                    highlight()
+                } else {
+                    plain()
+                };
+                self.window.plot(surface, c, x as u8, row as u8, color);
             }
         }
     }
 
     pub fn arrow_left(&mut self) {
-        self.selected = match self.selected % 3 {
-            1..3 => self.selected - 1,
-            _ => self.selected,
+        let (cols, _) = self.grid();
+        if !self.selected.is_multiple_of(cols) {
+            self.selected -= 1;
         }
     }
 
     pub fn arrow_right(&mut self) {
-        self.selected = match self.selected % 3 {
-            0..2 => self.selected + 1,
-            _ => self.selected,
-        };
-        if self.selected >= self.num_files {
-            self.selected = self.num_files - 1;
+        let (cols, _) = self.grid();
+        if self.selected % cols < cols - 1 {
+            self.selected = usize::min(self.selected + 1, self.last());
         }
     }
 
+    // Ctrl-Left and Ctrl-Right go to the ends of the row
+    pub fn row_start(&mut self) {
+        let (cols, _) = self.grid();
+        self.selected -= self.selected % cols;
+    }
+
+    pub fn row_end(&mut self) {
+        let (cols, _) = self.grid();
+        let start = self.selected - self.selected % cols;
+        self.selected = usize::min(start + cols - 1, self.last());
+    }
+
     pub fn arrow_up(&mut self) {
-        if self.selected >= 3 {
-            self.selected -= 3;
+        let (cols, _) = self.grid();
+        if self.selected >= cols {
+            self.selected -= cols;
         }
     }
 
     pub fn arrow_down(&mut self) {
-        self.selected += 3;
-        if self.selected >= self.num_files {
-            self.selected = self.num_files - 1;
+        let (cols, _) = self.grid();
+        self.selected = usize::min(self.selected + cols, self.last());
+    }
+
+    // A page is as many names as fit in the window
+    pub fn page_up(&mut self) {
+        self.selected = self.selected.saturating_sub(self.per_page());
+    }
+
+    pub fn page_down(&mut self) {
+        self.selected = usize::min(self.selected + self.per_page(), self.last());
+    }
+
+    pub fn is_prompting(&self) -> bool {
+        self.prompt.is_some()
+    }
+
+    pub fn new_file(&mut self) {
+        self.prompt = Some((Action::New, Prompt::default()));
+    }
+
+    pub fn copy(&mut self) {
+        if self.num_files > 0 && !self.is_dir_selected() {
+            self.prompt = Some((Action::Copy, Prompt::default()));
         }
     }
+
+    // A directory only goes once there's nothing in it
+    pub fn delete(&mut self) {
+        if self.num_files > 0 {
+            self.deleting = true;
+        }
+    }
+
+    pub fn is_confirming(&self) -> bool {
+        self.deleting
+    }
+
+    pub fn confirm_delete(&mut self, c: char, fs: &mut FsType) {
+        self.deleting = false;
+        if c != 'y' {
+            return;
+        }
+        let name = self.name();
+        let name = name.as_str().unwrap_or("");
+        let mut status = ArrayString::<64>::default();
+        let deleted = if self.is_dir_selected() {
+            fs.rmdir(name)
+        } else {
+            fs.delete(name)
+        };
+        let _ = match deleted {
+            Ok(()) => {
+                self.files_changed = true;
+                write!(status, "Deleted {name}")
+            }
+            Err(e) => write!(status, "ERROR {e}"),
+        };
+        self.status = Some(status);
+    }
+
+    // A directory takes everything in it along
+    pub fn rename(&mut self) {
+        if self.num_files > 0 {
+            self.prompt = Some((Action::Rename, Prompt::default()));
+        }
+    }
+
+    pub fn prompt_char(&mut self, c: char) {
+        if let Some((_, name)) = &mut self.prompt {
+            name.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self, fs: &mut FsType) {
+        match &mut self.prompt {
+            Some((_, name)) => {
+                name.pop();
+            }
+            None => self.up(fs),
+        }
+    }
+
+    pub fn escape(&mut self) {
+        self.prompt = None;
+        self.deleting = false;
+    }
+
+    pub fn newline(&mut self, fs: &mut FsType) {
+        let Some((action, typed)) = self.prompt.take() else {
+            return self.enter(fs);
+        };
+        // names are typed relative to the directory we're in, unless they
+        // start with '/'. A '/' on the end of a new one makes a directory.
+        let typed = typed.as_str();
+        let dir = if typed.starts_with('/') { "" } else { self.dir.as_str() };
+        let mut path = Prompt::<MAX_PATH_BYTES>::default();
+        let fits = dir.chars().chain(typed.chars()).all(|c| path.push(c));
+        let (name, mkdir) = match path.as_str().strip_suffix('/') {
+            Some(name) if matches!(action, Action::New) => (name, true),
+            _ => (path.as_str(), false),
+        };
+
+        let mut status = ArrayString::<64>::default();
+        let _ = if typed.is_empty() {
+            write!(status, "ERROR File name must be at least one character")
+        } else if !fits {
+            write!(status, "ERROR path too long")
+        } else if fs::is_hidden(typed) {
+            write!(status, "ERROR names starting with . are hidden")
+        } else if fs.exists(name) {
+            // open_create would quietly empty it
+            write!(status, "ERROR {name} already exists")
+        } else {
+            let done = match action {
+                Action::New if mkdir => fs.mkdir(name),
+                Action::New => fs.open_create(name).and_then(|fd| fs.close(fd)),
+                Action::Copy => self.copy_selected(name, fs),
+                Action::Rename => fs.rename(self.name().as_str().unwrap_or(""), name),
+            };
+            match done {
+                Ok(()) => {
+                    self.files_changed = true;
+                    match action {
+                        Action::New => write!(status, "Created {}", path.as_str()),
+                        Action::Copy => write!(status, "Copied to {name}"),
+                        Action::Rename => write!(status, "Renamed to {name}"),
+                    }
+                }
+                Err(e) => write!(status, "ERROR {e}"),
+            }
+        };
+        self.status = Some(status);
+    }
+
+    fn copy_selected(&mut self, name: &str, fs: &mut FsType) -> Result<(), FileSystemError> {
+        let written = with_file_buffer(|buffer| {
+            let n = self.read_selected(buffer, fs)?;
+            let fd = fs.open_create(name)?;
+            let written = fs.write(fd, &buffer[..n]);
+            fs.close(fd)?;
+            Ok::<_, FileSystemError>(written)
+        })?;
+        // an empty copy isn't worth keeping
+        if written.is_err() {
+            let _ = fs.delete(name);
+        }
+        written
+    }
+}
+
+// A name from the directory listing without the zeros after it
+fn trimmed(name: &[u8; MAX_FILENAME_BYTES]) -> &[u8] {
+    let end = name
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(MAX_FILENAME_BYTES);
+    &name[..end]
 }
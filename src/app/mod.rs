@@ -1,14 +1,20 @@
+use check::Check;
+use clipboard::Clipboard;
 use editor::TextEditor;
+pub(crate) use editor::SAVE_FILE;
 use explorer::Explorer;
 use script::RunningScript;
 use simple_interp::{ArrayString, Interpreter};
 use window::Window;
 
-use crate::{FsType, MAX_FILE_BYTES};
+use crate::{scratch::with_file_buffer, surface::Surface, FsType, MAX_PATH_BYTES};
 use core::fmt::Write;
 
+pub mod check;
+pub mod clipboard;
 mod editor;
 pub mod explorer;
+mod prompt;
 mod script;
 pub mod window;
 
@@ -18,55 +24,80 @@ pub mod window;
 // to figure out how to make an array of different
 // trait objects on bare metal, and this adds only a small
 // amount of code compared to the headache that it saved.
+#[allow(clippy::large_enum_variant)]
 pub enum App {
     TextEditor(TextEditor),
     Explorer(Explorer),
     RunningScript(RunningScript),
+    Check(Check),
 }
 
 impl App {
-    pub fn exit(&self, fs: &mut FsType) -> (Window, ArrayString<64>) {
-        let mut a = ArrayString::<64>::default();
+    // The Explorer to put in this app's place, or None if the app isn't ready to
+    // close yet. An editor with unsaved changes asks what to do with them first.
+    pub fn exit(&mut self, fs: &mut FsType) -> Option<App> {
+        if let App::TextEditor(text_editor) = self {
+            if !text_editor.exit() {
+                return None;
+            }
+        }
+        Some(self.explorer(fs))
+    }
+
+    // An Explorer for this app's window, in the directory of the
+    // file it had open and with that file selected
+    fn explorer(&self, fs: &mut FsType) -> App {
+        let path = match self {
+            App::TextEditor(text_editor) => text_editor.filename.as_str().unwrap_or(""),
+            App::RunningScript(running_script) => running_script.filename.as_str().unwrap_or(""),
+            App::Explorer(_) | App::Check(_) => "",
+        };
+        App::Explorer(Explorer::showing(self.window().clone(), path, fs))
+    }
+
+    pub fn save(&mut self, fs: &mut FsType) {
         match self {
             App::TextEditor(text_editor) => {
-                if let Ok(filename) = text_editor.filename.as_str() {
-                    let mut buffer = [0u8; MAX_FILE_BYTES];
-                    let len = text_editor.dump(&mut buffer);
+                text_editor.save(fs);
+            }
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
 
-                    let full = fs
-                        .open_create(filename)
-                        .and_then(|fd| fs.write(fd, &buffer[..len]).and_then(|_| fs.close(fd)));
+    pub fn save_as(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.save_as(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
 
-                    match full {
-                        Ok(()) => (text_editor.window.clone(), a),
-                        Err(e) => {
-                            let _ = write!(a, "couldn't save: {e}");
-                            (text_editor.window.clone(), a)
-                        }
-                    }
-                } else {
-                    let _ = write!(a, "couldn't save: bad file name");
-                    (text_editor.window.clone(), a)
-                }
-            }
-            App::Explorer(explorer) => (explorer.window.clone(), a),
-            App::RunningScript(running_script) => (running_script.window.clone(), a),
+    pub fn open(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.open(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        match self {
+            App::TextEditor(text_editor) => &text_editor.window,
+            App::Explorer(explorer) => &explorer.window,
+            App::RunningScript(running_script) => &running_script.window,
+            App::Check(check) => &check.window,
         }
     }
 
     pub fn title(&self) -> ArrayString<64> {
         let mut a = ArrayString::<64>::default();
         match self {
-            App::TextEditor(text) => {
-                let _ = write!(
-                    a,
-                    "EDIT:{},F6 to exit",
-                    text.filename.as_str().unwrap_or("INVALID_NAME")
-                );
-            }
-            App::Explorer(_) => {
-                let _ = write!(a, "(e)dit,(r)un");
-            }
+            App::TextEditor(text) => a = text.title(),
+            App::Explorer(explorer) => a = explorer.title(),
             App::RunningScript(script) => {
                 let _ = write!(
                     a,
@@ -74,114 +105,400 @@ impl App {
                     script.filename.as_str().unwrap_or("INVALID_NAME")
                 );
             }
+            App::Check(check) => a = check.title(),
         };
         a
     }
-    pub fn arrow_left(&mut self) {
+    pub fn arrow_left(&mut self, select: bool) {
         match self {
-            App::TextEditor(text_editor) => text_editor.arrow_left(),
+            App::TextEditor(text_editor) => text_editor.arrow_left(select),
             App::Explorer(explorer) => explorer.arrow_left(),
             App::RunningScript(_) => {}
+            App::Check(_) => {}
         }
     }
 
-    pub fn arrow_right(&mut self) {
+    pub fn arrow_right(&mut self, select: bool) {
         match self {
-            App::TextEditor(text_editor) => text_editor.arrow_right(),
+            App::TextEditor(text_editor) => text_editor.arrow_right(select),
             App::Explorer(explorer) => explorer.arrow_right(),
             App::RunningScript(_) => {}
+            App::Check(_) => {}
         }
     }
 
-    pub fn arrow_up(&mut self) {
+    pub fn arrow_up(&mut self, select: bool) {
         match self {
-            App::TextEditor(text_editor) => text_editor.arrow_up(),
+            App::TextEditor(text_editor) => text_editor.arrow_up(select),
             App::Explorer(explorer) => explorer.arrow_up(),
             App::RunningScript(_) => {}
+            App::Check(_) => {}
         }
     }
 
-    pub fn arrow_down(&mut self) {
+    pub fn arrow_down(&mut self, select: bool) {
         match self {
-            App::TextEditor(text_editor) => text_editor.arrow_down(),
+            App::TextEditor(text_editor) => text_editor.arrow_down(select),
             App::Explorer(explorer) => explorer.arrow_down(),
             App::RunningScript(_) => {}
+            App::Check(_) => {}
         }
     }
 
-    pub fn newline(&mut self) -> Option<App> {
+    pub fn home(&mut self, select: bool) {
         match self {
-            App::TextEditor(text_editor) => text_editor.newline(),
+            App::TextEditor(text_editor) => text_editor.home(select),
             App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn end(&mut self, select: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.end(select),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn page_up(&mut self, select: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.page_up(select),
+            App::Explorer(explorer) => explorer.page_up(),
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn page_down(&mut self, select: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.page_down(select),
+            App::Explorer(explorer) => explorer.page_down(),
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn word_left(&mut self, select: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.word_left(select),
+            App::Explorer(explorer) => explorer.row_start(),
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn word_right(&mut self, select: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.word_right(select),
+            App::Explorer(explorer) => explorer.row_end(),
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn newline(&mut self, fs: &mut FsType) -> Option<App> {
+        match self {
+            App::TextEditor(text_editor) => {
+                if text_editor.newline(fs) {
+                    return Some(self.explorer(fs));
+                }
+            }
+            App::Explorer(explorer) => explorer.newline(fs),
             App::RunningScript(running_script) => running_script.input('\n'),
+            App::Check(_) => {}
         }
         None
     }
 
-    pub fn backspace(&mut self) -> Option<App> {
+    pub fn shift_newline(&mut self, fs: &mut FsType) -> Option<App> {
+        if let App::TextEditor(text_editor) = self {
+            if text_editor.shift_newline(fs) {
+                return Some(self.explorer(fs));
+            }
+            None
+        } else {
+            self.newline(fs)
+        }
+    }
+
+    pub fn backspace(&mut self, fs: &mut FsType) -> Option<App> {
         match self {
             App::TextEditor(text_editor) => text_editor.backspace(),
-            App::Explorer(_) => {}
+            App::Explorer(explorer) => explorer.backspace(fs),
             App::RunningScript(running_script) => running_script.input('\u{8}'),
+            App::Check(_) => {}
         }
         None
     }
 
-    pub fn insert_char(&mut self, c: char, fs: &mut FsType) -> Option<App> {
+    // Only the editor can delete forwards, everything else treats it as a backspace
+    pub fn delete(&mut self, fs: &mut FsType) -> Option<App> {
         match self {
             App::TextEditor(text_editor) => {
-                text_editor.insert_char(c);
+                text_editor.delete();
+                None
+            }
+            _ => self.backspace(fs),
+        }
+    }
+
+    pub fn indent(&mut self, outdent: bool) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.indent(outdent),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn go_to(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.go_to(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn find(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.find(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn replace(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.replace(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn copy(&mut self, clipboard: &mut Clipboard) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.copy(clipboard),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn cut(&mut self, clipboard: &mut Clipboard) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.cut(clipboard),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    // A running script gets the clipboard typed in as its input
+    pub fn paste(&mut self, clipboard: &Clipboard) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.paste(clipboard),
+            App::Explorer(_) => {}
+            App::RunningScript(running_script) => {
+                for c in clipboard.as_bytes() {
+                    running_script.input(*c as char);
+                }
+            }
+            App::Check(_) => {}
+        }
+    }
+
+    // Anything the app wants shown in the status bar since we last asked
+    pub fn take_status(&mut self) -> Option<ArrayString<64>> {
+        match self {
+            App::TextEditor(text_editor) => text_editor.take_status(),
+            App::Explorer(explorer) => explorer.take_status(),
+            App::RunningScript(running_script) => running_script.take_message(),
+            App::Check(_) => None,
+        }
+    }
+
+    // Whether the app wrote any files since we last asked
+    pub fn take_files_changed(&mut self) -> bool {
+        match self {
+            App::TextEditor(text_editor) => text_editor.take_files_changed(),
+            App::Explorer(explorer) => explorer.take_files_changed(),
+            App::RunningScript(_) => false,
+            App::Check(_) => false,
+        }
+    }
+
+    pub fn escape(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.escape(),
+            App::Explorer(explorer) => explorer.escape(),
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn toggle_gutter(&mut self) {
+        if let App::TextEditor(text_editor) = self {
+            text_editor.toggle_gutter()
+        }
+    }
+
+    pub fn toggle_status_line(&mut self) {
+        if let App::TextEditor(text_editor) = self {
+            text_editor.toggle_status_line()
+        }
+    }
+
+    pub fn cycle_wrap(&mut self) {
+        if let App::TextEditor(text_editor) = self {
+            text_editor.cycle_wrap()
+        }
+    }
+
+    pub fn toggle_vi(&mut self) {
+        if let App::TextEditor(text_editor) = self {
+            text_editor.toggle_vi()
+        }
+    }
+
+    pub fn undo(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.undo(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn redo(&mut self) {
+        match self {
+            App::TextEditor(text_editor) => text_editor.redo(),
+            App::Explorer(_) => {}
+            App::RunningScript(_) => {}
+            App::Check(_) => {}
+        }
+    }
+
+    pub fn insert_char(
+        &mut self,
+        c: char,
+        fs: &mut FsType,
+        clipboard: &mut Clipboard,
+    ) -> Option<App> {
+        match self {
+            App::TextEditor(text_editor) => {
+                if text_editor.insert_char(c, fs, clipboard) {
+                    Some(self.explorer(fs))
+                } else {
+                    None
+                }
+            }
+            App::Explorer(explorer) if explorer.is_confirming() => {
+                explorer.confirm_delete(c, fs);
+                None
+            }
+            App::Explorer(explorer) if explorer.is_prompting() => {
+                explorer.prompt_char(c);
                 None
             }
             App::Explorer(explorer) => match c {
-                'r' => {
-                    let mut buf = [0u8; MAX_FILE_BYTES];
-                    if let Ok(n) = explorer.read_selected(&mut buf, fs) {
-                        if let Ok(contents) = str::from_utf8(&buf[..n]) {
-                            Some(App::RunningScript(RunningScript::new(
-                                explorer.window.clone(),
-                                explorer.name(),
-                                Interpreter::new(contents),
-                            )))
-                        } else {
+                'e' | 'r' if explorer.is_dir_selected() => {
+                    explorer.enter(fs);
+                    None
+                }
+                'r' => with_file_buffer(|buf| match explorer.read_selected(buf, fs) {
+                    Ok(n) => match str::from_utf8(&buf[..n]) {
+                        Ok(contents) => Some(App::RunningScript(RunningScript::new(
+                            explorer.window.clone(),
+                            explorer.name(),
+                            Interpreter::new(contents),
+                        ))),
+                        Err(_) => {
+                            explorer.error(format_args!("{} isn't text", explorer.name()));
                             None
                         }
-                    } else {
+                    },
+                    Err(e) => {
+                        explorer.error(e);
                         None
                     }
-                }
+                }),
                 'e' => {
                     let mut text = TextEditor::new(explorer.window.clone(), explorer.name());
-
-                    let mut buf = [0u8; MAX_FILE_BYTES];
-                    if let Ok(n) = explorer.read_selected(&mut buf, fs) {
-                        for c in &buf[..n] {
-                            let c = *c as char;
-                            if c == '\n' {
-                                text.newline();
-                            } else {
-                                text.insert_char(c);
-                            }
+                    with_file_buffer(|buf| match explorer.read_selected(buf, fs) {
+                        Ok(n) => {
+                            text.load(&buf[..n]);
+                            Some(App::TextEditor(text))
                         }
-                        Some(App::TextEditor(text))
-                    } else {
-                        None
-                    }
+                        Err(e) => {
+                            explorer.error(e);
+                            None
+                        }
+                    })
+                }
+                '+' => {
+                    explorer.new_file();
+                    None
+                }
+                'i' => {
+                    explorer.toggle_details(fs);
+                    None
+                }
+                'c' => {
+                    explorer.copy();
+                    None
+                }
+                'k' => Some(App::Check(Check::new(explorer.window.clone()))),
+                'd' => {
+                    explorer.delete();
+                    None
+                }
+                'n' => {
+                    explorer.rename();
+                    None
                 }
                 _ => None,
             },
-            App::RunningScript(running_script) => {
-                running_script.input(c);
+            App::RunningScript(running_script) => match running_script.error_line() {
+                Some(line) if c == 'g' => {
+                    let mut filename = ArrayString::<MAX_PATH_BYTES>::default();
+                    for c in running_script.filename.buffer_slice() {
+                        filename.push_char(*c as char);
+                    }
+                    match TextEditor::from_file(running_script.window.clone(), filename, fs) {
+                        Ok(mut text) => {
+                            text.go_to_line(line);
+                            Some(App::TextEditor(text))
+                        }
+                        Err(e) => {
+                            running_script.error(e);
+                            None
+                        }
+                    }
+                }
+                _ => {
+                    running_script.input(c);
+                    None
+                }
+            },
+            App::Check(check) => {
+                check.insert_char(c);
                 None
             }
         }
     }
 
-    pub fn draw(&mut self) {
+    pub fn draw(&mut self, surface: &mut impl Surface) {
         match self {
-            App::TextEditor(text_editor) => text_editor.draw(),
-            App::Explorer(explorer) => explorer.draw(),
-            App::RunningScript(running_script) => running_script.draw(),
+            App::TextEditor(text_editor) => text_editor.draw(surface),
+            App::Explorer(explorer) => explorer.draw(surface),
+            App::RunningScript(running_script) => running_script.draw(surface),
+            App::Check(check) => check.draw(surface),
         }
     }
 }
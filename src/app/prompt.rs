@@ -0,0 +1,57 @@
+// A short line of text typed into a title bar prompt, like the
+// query of a search. Unlike ArrayString it can also take
+// characters back off the end for Backspace.
+#[derive(Clone, Copy)]
+pub struct Prompt<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for Prompt<N> {
+    fn default() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize> Prompt<N> {
+    // false if the prompt is already full
+    pub fn push(&mut self, c: char) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.buf[self.len] = c as u8;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<char> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        Some(self.buf[self.len] as char)
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        str::from_utf8(self.as_bytes()).unwrap_or("")
+    }
+}
@@ -1,17 +1,27 @@
-use core::fmt::Write;
-use pluggable_interrupt_os::vga_buffer::{Color, ColorCode};
+use core::fmt::{Display, Write};
 use simple_interp::{ArrayString, InterpreterOutput, TickStatus};
 
-use crate::{InterpType, MAX_FILENAME_BYTES};
+use crate::{
+    surface::Surface,
+    vga::{Color, ColorCode},
+    InterpType, MAX_PATH_BYTES,
+};
 
 use super::window::Window;
 
+// How simple_interp starts the message for an error, before "N: what went wrong"
+const ERROR_PREFIX: &[u8] = b"Error on line ";
+
 pub struct RunningScript {
     pub window: Window,
-    pub filename: ArrayString<MAX_FILENAME_BYTES>,
+    pub filename: ArrayString<MAX_PATH_BYTES>,
     interpreter: InterpType,
     iobuffer: IOBuffer,
     status: TickStatus,
+    // the line the script failed on, once it has
+    error_line: Option<usize>,
+    // for the status bar, when g can't open the script
+    message: Option<ArrayString<64>>,
 }
 
 #[derive(Default)]
@@ -37,7 +47,7 @@ impl IOBuffer {
             None => false,
             Some(begin) => {
                 begin + 1 < self.buf.buffer_slice().len()
-                    && self.buf.buffer_slice().last() == Some(&('\n' as u8))
+                    && self.buf.buffer_slice().last() == Some(&b'\n')
             }
         }
     }
@@ -54,17 +64,18 @@ impl InterpreterOutput for IOBuffer {
 impl RunningScript {
     pub fn new(
         window: Window,
-        filename: ArrayString<MAX_FILENAME_BYTES>,
+        filename: ArrayString<MAX_PATH_BYTES>,
         interpreter: InterpType,
     ) -> Self {
         let outbuffer = Default::default();
-        window.clear();
         Self {
             window,
             filename,
             interpreter,
             iobuffer: outbuffer,
             status: TickStatus::Continuing,
+            error_line: None,
+            message: None,
         }
     }
 
@@ -74,10 +85,17 @@ impl RunningScript {
     pub fn tick(&mut self) -> bool {
         match self.status {
             TickStatus::Continuing => {
+                let printed = self.iobuffer.buf.len();
                 self.status = self.interpreter.tick(&mut self.iobuffer);
                 if let TickStatus::AwaitInput = self.status {
                     self.iobuffer.begin_input();
                 }
+                if let TickStatus::Finished = self.status {
+                    self.error_line = self.find_error_line(printed);
+                    if let Some(line) = self.error_line {
+                        let _ = write!(self.iobuffer.buf, "\n(g) to edit line {line}");
+                    }
+                }
                 true
             }
             TickStatus::Finished => false,
@@ -96,7 +114,7 @@ impl RunningScript {
         }
     }
 
-    pub fn draw(&mut self) {
+    pub fn draw<S: Surface>(&mut self, surface: &mut S) {
         let color = ColorCode::new(Color::LightGray, Color::Black);
         let color_inv = ColorCode::new(Color::Black, Color::LightGray);
 
@@ -112,7 +130,7 @@ impl RunningScript {
                 count += 1;
                 lasti = i;
             }
-            if slice[i] == '\n' as u8 {
+            if slice[i] == b'\n' {
                 count += 1;
                 lasti = i;
             }
@@ -124,8 +142,9 @@ impl RunningScript {
 
         // dump everything from the buffer to screen, starting at start
         let mut cursor = 0;
-        let pplot = |c, cursor| {
+        let pplot = |surface: &mut S, c, cursor| {
             self.window.plot(
+                surface,
                 c,
                 (cursor % self.window.width()) as u8,
                 (cursor / self.window.width()) as u8,
@@ -137,18 +156,19 @@ impl RunningScript {
             if c == '\n' {
                 let tmp = cursor + self.window.width();
                 let tmp = tmp - tmp % self.window.width();
-                for _ in cursor..tmp {
-                    pplot(' ', cursor);
+                while cursor < tmp {
+                    pplot(surface, ' ', cursor);
                     cursor += 1;
                 }
             } else {
-                pplot(c, cursor);
+                pplot(surface, c, cursor);
                 cursor += 1;
             }
         }
 
         // draw cursor block
         self.window.plot(
+            surface,
             ' ',
             (cursor % self.window.width()) as u8,
             (cursor / self.window.width()) as u8,
@@ -158,11 +178,39 @@ impl RunningScript {
 
         // clear the rest
         while cursor < self.window.width() * self.window.height() {
-            pplot(' ', cursor);
+            pplot(surface, ' ', cursor);
             cursor += 1;
         }
     }
 
+    pub fn error_line(&self) -> Option<usize> {
+        self.error_line
+    }
+
+    pub fn error(&mut self, e: impl Display) {
+        let mut message = ArrayString::<64>::default();
+        let _ = write!(message, "ERROR {e}");
+        self.message = Some(message);
+    }
+
+    pub fn take_message(&mut self) -> Option<ArrayString<64>> {
+        self.message.take()
+    }
+
+    // simple_interp only tells us about an error by printing it on the
+    // tick that stops the script, so only what that tick printed counts,
+    // and only when it's the interpreter's own message. A script that
+    // prints something with "line 3" in it isn't an error.
+    fn find_error_line(&self, printed: usize) -> Option<usize> {
+        let out = &self.iobuffer.buf.buffer_slice()[printed..];
+        let rest = out.strip_prefix(ERROR_PREFIX)?;
+        let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 || rest.get(digits) != Some(&b':') {
+            return None;
+        }
+        str::from_utf8(&rest[..digits]).ok()?.parse().ok()
+    }
+
     pub fn input(&mut self, c: char) {
         self.iobuffer.buf.push_char(c);
     }
@@ -1,4 +1,4 @@
-use pluggable_interrupt_os::vga_buffer::{plot, Color, ColorCode};
+use crate::{surface::Surface, vga::ColorCode};
 
 #[derive(Debug, Clone)]
 pub struct Window {
@@ -34,7 +34,7 @@ impl Window {
     //     plot('#', self.x2 as usize, self.y2 as usize, dbgcolor);
     // }
 
-    pub fn plot(&self, c: char, col: u8, row: u8, color: ColorCode) {
+    pub fn plot(&self, surface: &mut impl Surface, c: char, col: u8, row: u8, color: ColorCode) {
         let col = col + self.x1;
         let row = row + self.y1;
         // In release mode these don't do anything, so realistically,
@@ -44,15 +44,6 @@ impl Window {
         // soon as something goes wrong in the debug builds.
         debug_assert!(self.x1 <= col && col <= self.x2);
         debug_assert!(self.y1 <= row && row <= self.y2);
-        plot(c, col as usize, row as usize, color);
-    }
-
-    pub fn clear(&self) {
-        let color = ColorCode::new(Color::LightGray, Color::Black);
-        for x in 0..self.width() {
-            for y in 0..self.height() {
-                self.plot(' ', x as u8, y as u8, color);
-            }
-        }
+        surface.plot(c, col as usize, row as usize, color);
     }
 }
@@ -0,0 +1,140 @@
+// Packs files from the host into a disk image the OS can load, and gets
+// them back out again.
+//
+//   swimimg pack <image> <file or directory>...
+//   swimimg unpack <image> <directory>
+//   swimimg list <image>
+//
+// Files keep the order they're given in, which is the order the Explorer
+// shows them. A directory adds everything in it sorted by name, with its
+// subdirectories becoming directories on the image too, so lib/sum is sum
+// in lib.
+//
+// The image works as seed.img, or as the disk for QEMU's -drive. It's
+// packed as small as it'll go, so pad it out first to leave room to write.
+
+use std::{
+    env, fs,
+    path::{Component, Path},
+    process::ExitCode,
+};
+
+use simple_swim_template::{
+    storage::{self, MemDisk},
+    MAX_FILENAME_BYTES,
+};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["pack", image, ref inputs @ ..] if !inputs.is_empty() => pack(image, inputs),
+        ["unpack", image, dir] => unpack(image, dir),
+        ["list", image] => list(image),
+        _ => Err(
+            "usage: swimimg pack <image> <file or directory>...\n       \
+             swimimg unpack <image> <directory>\n       \
+             swimimg list <image>"
+                .into(),
+        ),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn pack(image: &str, inputs: &[&str]) -> Result<(), String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            add_dir(path, "", &mut files)?;
+        } else {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(format!("{input}: bad file name"))?;
+            files.push((name.to_string(), read(path)?));
+        }
+    }
+
+    let disk = storage::pack(
+        files
+            .iter()
+            .map(|(name, data)| (name.as_str(), data.as_slice())),
+    )
+    .map_err(|e| format!("{image}: {e}"))?;
+    fs::write(image, disk.to_bytes()).map_err(|e| format!("{image}: {e}"))?;
+    println!("packed {} files into {image}", files.len());
+    Ok(())
+}
+
+fn add_dir(dir: &Path, prefix: &str, files: &mut Vec<(String, Vec<u8>)>) -> Result<(), String> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{}: {e}", dir.display()))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|_| format!("{}: bad file name", path.display()))?;
+        if path.is_dir() {
+            add_dir(&path, &format!("{prefix}{name}/"), files)?;
+        } else {
+            files.push((format!("{prefix}{name}"), read(&path)?));
+        }
+    }
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn open(image: &str) -> Result<MemDisk, String> {
+    let bytes = fs::read(image).map_err(|e| format!("{image}: {e}"))?;
+    Ok(MemDisk::from_bytes(&bytes))
+}
+
+fn unpack(image: &str, dir: &str) -> Result<(), String> {
+    let files = storage::unpack(open(image)?).map_err(|e| format!("{image}: {e}"))?;
+    // an image can come from anywhere, so nothing in it gets to
+    // write outside dir, and nothing is written if any name is bad
+    for (name, _) in &files {
+        check_name(name).map_err(|e| format!("{image}: {name:?} {e}"))?;
+    }
+    for (name, data) in &files {
+        let path = Path::new(dir).join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+        }
+        fs::write(&path, data).map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    println!("unpacked {} files into {dir}", files.len());
+    Ok(())
+}
+
+fn check_name(name: &str) -> Result<(), &'static str> {
+    let path = Path::new(name);
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err("isn't a plain relative name");
+    }
+    // only each name on the way has to fit, not the whole path
+    if path.iter().any(|name| name.len() > MAX_FILENAME_BYTES) {
+        return Err("is too long");
+    }
+    Ok(())
+}
+
+fn list(image: &str) -> Result<(), String> {
+    let files = storage::unpack(open(image)?).map_err(|e| format!("{image}: {e}"))?;
+    for (name, data) in &files {
+        println!("{name:<20} {:>5}B", data.len());
+    }
+    Ok(())
+}
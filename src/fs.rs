@@ -0,0 +1,1123 @@
+// The file system everything is kept in, laid out on any BlockDevice
+// with BLOCK_SIZE blocks:
+//
+//   block 0          a bit for each inode, set while it's in use, and
+//                    MAGIC in the last few bytes once it's formatted
+//   block 1          a bit for each block, set while it's in use
+//   blocks 2..       the inode table
+//   the rest         file data
+//
+// An inode is how many bytes its file holds, whether it's a directory,
+// then the blocks they're in, one byte per block number. A directory is
+// a file of ENTRY_BYTES entries, each a MAX_FILENAME_BYTES name and the
+// inode it's for. Removing one zeros it, and the next name added takes
+// its place, so a directory only grows when it's full. Inode 0 is the
+// top directory, the only thing without a name.
+//
+// Paths are names joined by '/', starting from the top directory. Only
+// each name has to fit in MAX_FILENAME_BYTES, not the whole path.
+//
+// Nothing is kept outside the disk but the open files, so what's on the
+// disk is the whole story, which is what lets fsck check it. Every change
+// goes straight to the disk, and close waits for it to land.
+
+use core::fmt;
+
+use crate::{
+    storage::{BlockDevice, DiskError},
+    BLOCK_SIZE, MAX_FILENAME_BYTES, MAX_FILES_STORED, MAX_FILE_BLOCKS, MAX_FILE_BYTES, MAX_OPEN,
+    NUM_BLOCKS,
+};
+
+const INODE_MAP: usize = 0;
+const BLOCK_MAP: usize = 1;
+const INODE_TABLE: usize = 2;
+
+const MAGIC: &[u8; 8] = b"SWIMFS02";
+
+pub const NUM_INODES: usize = MAX_FILES_STORED + 1;
+pub const ROOT: usize = 0;
+pub const ENTRY_BYTES: usize = MAX_FILENAME_BYTES + 1;
+
+const INODE_BYTES: usize = 3 + MAX_FILE_BLOCKS;
+const INODES_PER_BLOCK: usize = BLOCK_SIZE / INODE_BYTES;
+pub const FIRST_DATA_BLOCK: usize = INODE_TABLE + NUM_INODES.div_ceil(INODES_PER_BLOCK);
+
+const _: () = assert!(NUM_BLOCKS <= u8::MAX as usize + 1);
+const _: () = assert!(NUM_INODES <= u8::MAX as usize + 1);
+const _: () = assert!(NUM_BLOCKS <= BLOCK_SIZE * 8 && NUM_INODES <= BLOCK_SIZE * 8);
+const _: () = assert!(NUM_INODES.div_ceil(8) <= BLOCK_SIZE - MAGIC.len());
+const _: () = assert!(MAX_FILE_BYTES <= u16::MAX as usize);
+const _: () = assert!(NUM_INODES * ENTRY_BYTES <= MAX_FILE_BYTES);
+
+pub type Name = [u8; MAX_FILENAME_BYTES];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSystemError {
+    FileNotFound,
+    AlreadyOpen,
+    TooManyOpen,
+    TooManyFiles,
+    NotOpen,
+    NotOpenForRead,
+    NotOpenForWrite,
+    DiskFull,
+    FileTooBig,
+    BadFilename,
+    // its inode points off the disk, see Inode::blocks_in_use
+    Damaged,
+    // no MAGIC, so most likely a blank disk
+    NotFormatted,
+    Disk(DiskError),
+    IsADirectory,
+    NotADirectory,
+    NotEmpty,
+    AlreadyExists,
+    // a directory can't be moved somewhere inside itself
+    IntoItself,
+}
+
+impl From<DiskError> for FileSystemError {
+    fn from(e: DiskError) -> Self {
+        FileSystemError::Disk(e)
+    }
+}
+
+impl fmt::Display for FileSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileSystemError::FileNotFound => write!(f, "file not found"),
+            FileSystemError::AlreadyOpen => write!(f, "file already open"),
+            FileSystemError::TooManyOpen => write!(f, "too many open files"),
+            FileSystemError::TooManyFiles => write!(f, "no room for more files"),
+            FileSystemError::NotOpen => write!(f, "file not open"),
+            FileSystemError::NotOpenForRead => write!(f, "not open for reading"),
+            FileSystemError::NotOpenForWrite => write!(f, "not open for writing"),
+            FileSystemError::DiskFull => write!(f, "disk full"),
+            FileSystemError::FileTooBig => write!(f, "file too big"),
+            FileSystemError::BadFilename => write!(f, "bad file name"),
+            FileSystemError::Damaged => write!(f, "file is damaged"),
+            FileSystemError::NotFormatted => write!(f, "disk isn't formatted"),
+            FileSystemError::Disk(e) => write!(f, "{e}"),
+            FileSystemError::IsADirectory => write!(f, "that's a directory"),
+            FileSystemError::NotADirectory => write!(f, "not a directory"),
+            FileSystemError::NotEmpty => write!(f, "directory isn't empty"),
+            FileSystemError::AlreadyExists => write!(f, "already exists"),
+            FileSystemError::IntoItself => write!(f, "can't move a directory into itself"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Inode {
+    pub bytes: usize,
+    pub is_dir: bool,
+    pub blocks: [u8; MAX_FILE_BLOCKS],
+}
+
+impl Inode {
+    pub const EMPTY: Inode = Inode {
+        bytes: 0,
+        is_dir: false,
+        blocks: [0; MAX_FILE_BLOCKS],
+    };
+
+    pub fn num_blocks(&self) -> usize {
+        self.bytes.div_ceil(BLOCK_SIZE)
+    }
+
+    // The blocks holding the file, or None if it's too big
+    // or any of them are outside the data blocks
+    pub fn blocks_in_use(&self) -> Option<&[u8]> {
+        let blocks = self.blocks.get(..self.num_blocks())?;
+        blocks
+            .iter()
+            .all(|b| (FIRST_DATA_BLOCK..NUM_BLOCKS).contains(&(*b as usize)))
+            .then_some(blocks)
+    }
+
+    // How many entries there's room for, if it's a directory
+    pub fn slots(&self) -> usize {
+        self.bytes / ENTRY_BYTES
+    }
+}
+
+// One thing in a directory, as list_directory hands them out
+#[derive(Clone, Copy)]
+pub struct DirEntry {
+    pub name: Name,
+    pub is_dir: bool,
+}
+
+impl DirEntry {
+    pub const EMPTY: DirEntry = DirEntry {
+        name: [0; MAX_FILENAME_BYTES],
+        is_dir: false,
+    };
+}
+
+#[derive(Clone, Copy)]
+struct Open {
+    inode: usize,
+    at: usize,
+    writing: bool,
+}
+
+// Whether disk has been formatted, so it can be mounted as it is
+pub fn is_formatted(disk: &impl BlockDevice<BLOCK_SIZE>) -> Result<bool, DiskError> {
+    if disk.num_blocks() < NUM_BLOCKS {
+        return Err(DiskError::TooSmall);
+    }
+    let mut buffer = [0; BLOCK_SIZE];
+    disk.read_block(INODE_MAP, &mut buffer)?;
+    Ok(buffer.ends_with(MAGIC))
+}
+
+pub struct FileSystem<D> {
+    disk: D,
+    open: [Option<Open>; MAX_OPEN],
+}
+
+impl<D: BlockDevice<BLOCK_SIZE>> FileSystem<D> {
+    // Formats disk, leaving it with just the empty directory. The kernel
+    // only ever mounts, see storage::seed.
+    #[cfg(any(test, feature = "std"))]
+    pub fn new(disk: D) -> Result<Self, FileSystemError> {
+        let mut fs = Self::mount(disk);
+        fs.format()?;
+        Ok(fs)
+    }
+
+    // Takes disk as it is, see is_formatted
+    pub fn mount(disk: D) -> Self {
+        Self {
+            disk,
+            open: [None; MAX_OPEN],
+        }
+    }
+
+    pub fn disk(&self) -> &D {
+        &self.disk
+    }
+
+    pub fn disk_mut(&mut self) -> &mut D {
+        &mut self.disk
+    }
+
+    #[cfg(any(test, feature = "std"))]
+    pub fn into_disk(self) -> D {
+        self.disk
+    }
+
+    // Starts over with the empty directory, forgetting every file
+    pub fn format(&mut self) -> Result<(), FileSystemError> {
+        self.open = [None; MAX_OPEN];
+        let zeros = [0; BLOCK_SIZE];
+        for block in 0..FIRST_DATA_BLOCK {
+            self.disk.write_block(block, &zeros)?;
+        }
+        for block in 0..FIRST_DATA_BLOCK {
+            self.set_block_used(block, true)?;
+        }
+        self.set_inode_used(ROOT, true)?;
+        let root = Inode {
+            is_dir: true,
+            ..Inode::EMPTY
+        };
+        self.set_inode(ROOT, &root)?;
+        // only once everything else is there
+        let mut buffer = [0; BLOCK_SIZE];
+        self.disk.read_block(INODE_MAP, &mut buffer)?;
+        buffer[BLOCK_SIZE - MAGIC.len()..].copy_from_slice(MAGIC);
+        self.disk.write_block(INODE_MAP, &buffer)?;
+        Ok(self.disk.flush()?)
+    }
+
+    pub fn open_read(&mut self, path: &str) -> Result<usize, FileSystemError> {
+        let inode = self.find(path)?;
+        let contents = self.inode(inode)?;
+        if contents.is_dir {
+            return Err(FileSystemError::IsADirectory);
+        }
+        if contents.blocks_in_use().is_none() {
+            return Err(FileSystemError::Damaged);
+        }
+        self.open_fd(inode, false)
+    }
+
+    // Opens path for writing from the start, emptying it if it's already
+    // there and making it if it isn't. The directory it's in has to be.
+    pub fn open_create(&mut self, path: &str) -> Result<usize, FileSystemError> {
+        let (dir, name) = self.parent(path)?;
+        let inode = match self.lookup(dir, &name)? {
+            Some((_, inode)) => {
+                if self.inode(inode)?.is_dir {
+                    return Err(FileSystemError::IsADirectory);
+                }
+                if self.is_open(inode) {
+                    return Err(FileSystemError::AlreadyOpen);
+                }
+                self.truncate(inode)?;
+                inode
+            }
+            None => self.make(dir, &name, false)?,
+        };
+        self.open_fd(inode, true)
+    }
+
+    pub fn read(&mut self, fd: usize, buffer: &mut [u8]) -> Result<usize, FileSystemError> {
+        let open = self.get_open(fd)?;
+        if open.writing {
+            return Err(FileSystemError::NotOpenForRead);
+        }
+        let inode = self.inode(open.inode)?;
+        let n = usize::min(buffer.len(), inode.bytes.saturating_sub(open.at));
+        self.read_at(&inode, open.at, &mut buffer[..n])?;
+        self.set_at(fd, open.at + n);
+        Ok(n)
+    }
+
+    // Everything in buffer goes in, or none of it does
+    pub fn write(&mut self, fd: usize, buffer: &[u8]) -> Result<(), FileSystemError> {
+        let open = self.get_open(fd)?;
+        if !open.writing {
+            return Err(FileSystemError::NotOpenForWrite);
+        }
+        let end = open.at + buffer.len();
+        if end > MAX_FILE_BYTES {
+            return Err(FileSystemError::FileTooBig);
+        }
+        let mut inode = self.inode(open.inode)?;
+        self.grow(&mut inode, end)?;
+        self.write_at(&inode, open.at, buffer)?;
+        self.set_inode(open.inode, &inode)?;
+        self.set_at(fd, end);
+        Ok(())
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<(), FileSystemError> {
+        let open = self.open.get_mut(fd).and_then(|open| open.take());
+        let open = open.ok_or(FileSystemError::NotOpen)?;
+        if open.writing {
+            self.disk.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, path: &str) -> Result<(), FileSystemError> {
+        let (dir, name) = self.parent(path)?;
+        let (slot, inode) = self
+            .lookup(dir, &name)?
+            .ok_or(FileSystemError::FileNotFound)?;
+        if self.inode(inode)?.is_dir {
+            return Err(FileSystemError::IsADirectory);
+        }
+        if self.is_open(inode) {
+            return Err(FileSystemError::AlreadyOpen);
+        }
+        self.remove(dir, slot, inode)
+    }
+
+    // Makes an empty directory at path, in one that's already there
+    pub fn mkdir(&mut self, path: &str) -> Result<(), FileSystemError> {
+        let (dir, name) = self.parent(path)?;
+        if self.lookup(dir, &name)?.is_some() {
+            return Err(FileSystemError::AlreadyExists);
+        }
+        self.make(dir, &name, true)?;
+        Ok(self.disk.flush()?)
+    }
+
+    // Removes the directory at path, which has to be empty first
+    pub fn rmdir(&mut self, path: &str) -> Result<(), FileSystemError> {
+        let (dir, name) = self.parent(path)?;
+        let (slot, inode) = self
+            .lookup(dir, &name)?
+            .ok_or(FileSystemError::FileNotFound)?;
+        let contents = self.directory(inode)?;
+        for i in 0..contents.slots() {
+            if self.entry(&contents, i)?.0[0] != 0 {
+                return Err(FileSystemError::NotEmpty);
+            }
+        }
+        self.remove(dir, slot, inode)
+    }
+
+    // Gives from the path to, which can be in another directory, taking
+    // the place of any file already there. A file that gets replaced keeps
+    // its inode, and so its place in the listing, with from's blocks moved
+    // into it. Directories move with everything in them, but never take
+    // the place of anything.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), FileSystemError> {
+        let (from_dir, from_name) = self.parent(from)?;
+        let (slot, inode) = self
+            .lookup(from_dir, &from_name)?
+            .ok_or(FileSystemError::FileNotFound)?;
+        let (to_dir, to_name) = self.parent(to)?;
+        if self.is_open(inode) {
+            return Err(FileSystemError::AlreadyOpen);
+        }
+        let contents = self.inode(inode)?;
+        if contents.is_dir && self.is_within(to, inode)? {
+            return Err(FileSystemError::IntoItself);
+        }
+        match self.lookup(to_dir, &to_name)? {
+            Some((_, other)) if other == inode => Ok(()),
+            Some((_, other)) => {
+                let old = self.inode(other)?;
+                if contents.is_dir || old.is_dir {
+                    return Err(FileSystemError::AlreadyExists);
+                }
+                if self.is_open(other) {
+                    return Err(FileSystemError::AlreadyOpen);
+                }
+                // One write swaps the contents over, so the power going out
+                // leaves the old file or the new one, never half of each.
+                // Stopping after it leaves spare blocks or a spare copy behind.
+                self.set_inode(other, &contents)?;
+                for block in old.blocks_in_use().unwrap_or(&[]) {
+                    self.set_block_used(*block as usize, false)?;
+                }
+                self.set_entry(from_dir, slot, &[0; MAX_FILENAME_BYTES], 0)?;
+                self.set_inode(inode, &Inode::EMPTY)?;
+                self.set_inode_used(inode, false)?;
+                Ok(self.disk.flush()?)
+            }
+            None if to_dir == from_dir => {
+                self.set_entry(from_dir, slot, &to_name, inode)?;
+                Ok(self.disk.flush()?)
+            }
+            // in the new place before it's out of the old one,
+            // so it's never nowhere
+            None => {
+                self.add_entry(to_dir, &to_name, inode)?;
+                self.set_entry(from_dir, slot, &[0; MAX_FILENAME_BYTES], 0)?;
+                Ok(self.disk.flush()?)
+            }
+        }
+    }
+
+    // Everything in the directory at path, "" being the top one,
+    // in the order they were added
+    pub fn list_directory(
+        &self,
+        path: &str,
+    ) -> Result<(usize, [DirEntry; MAX_FILES_STORED]), FileSystemError> {
+        let contents = self.directory(self.find_dir(path)?)?;
+        let used = self.map(INODE_MAP)?;
+        let mut entries = [DirEntry::EMPTY; MAX_FILES_STORED];
+        let mut count = 0;
+        for slot in 0..contents.slots() {
+            let (name, inode) = self.entry(&contents, slot)?;
+            if name[0] != 0 && inode < NUM_INODES && bit(&used, inode) && count < entries.len() {
+                entries[count] = DirEntry {
+                    name,
+                    is_dir: self.inode(inode)?.is_dir,
+                };
+                count += 1;
+            }
+        }
+        Ok((count, entries))
+    }
+
+    // A disk that can't be read counts as not having it,
+    // and whatever's tried on it next says what went wrong
+    pub fn exists(&self, path: &str) -> bool {
+        self.find(path).is_ok()
+    }
+
+    // How many bytes are in path, straight from its inode
+    pub fn file_size(&self, path: &str) -> Result<usize, FileSystemError> {
+        let inode = self.find(path)?;
+        Ok(self.inode(inode)?.bytes)
+    }
+
+    pub fn free_blocks(&self) -> Result<usize, FileSystemError> {
+        let map = self.map(BLOCK_MAP)?;
+        Ok((FIRST_DATA_BLOCK..NUM_BLOCKS)
+            .filter(|b| !bit(&map, *b))
+            .count())
+    }
+
+    pub fn free_inodes(&self) -> Result<usize, FileSystemError> {
+        let map = self.map(INODE_MAP)?;
+        Ok((1..NUM_INODES).filter(|i| !bit(&map, *i)).count())
+    }
+
+    // What's below is the layout itself, for fsck to look at and patch up
+
+    pub fn inode_used(&self, inode: usize) -> Result<bool, FileSystemError> {
+        Ok(bit(&self.map(INODE_MAP)?, inode))
+    }
+
+    pub fn set_inode_used(&mut self, inode: usize, used: bool) -> Result<(), FileSystemError> {
+        self.set_bit(INODE_MAP, inode, used)
+    }
+
+    pub fn block_used(&self, block: usize) -> Result<bool, FileSystemError> {
+        Ok(bit(&self.map(BLOCK_MAP)?, block))
+    }
+
+    pub fn set_block_used(&mut self, block: usize, used: bool) -> Result<(), FileSystemError> {
+        self.set_bit(BLOCK_MAP, block, used)
+    }
+
+    pub fn inode(&self, inode: usize) -> Result<Inode, FileSystemError> {
+        let mut buffer = [0; BLOCK_SIZE];
+        self.disk
+            .read_block(INODE_TABLE + inode / INODES_PER_BLOCK, &mut buffer)?;
+        let at = inode % INODES_PER_BLOCK * INODE_BYTES;
+        let mut blocks = [0; MAX_FILE_BLOCKS];
+        blocks.copy_from_slice(&buffer[at + 3..at + INODE_BYTES]);
+        Ok(Inode {
+            bytes: u16::from_le_bytes([buffer[at], buffer[at + 1]]) as usize,
+            is_dir: buffer[at + 2] != 0,
+            blocks,
+        })
+    }
+
+    pub fn set_inode(&mut self, inode: usize, contents: &Inode) -> Result<(), FileSystemError> {
+        let block = INODE_TABLE + inode / INODES_PER_BLOCK;
+        let mut buffer = [0; BLOCK_SIZE];
+        self.disk.read_block(block, &mut buffer)?;
+        let at = inode % INODES_PER_BLOCK * INODE_BYTES;
+        buffer[at..at + 2].copy_from_slice(&(contents.bytes as u16).to_le_bytes());
+        buffer[at + 2] = contents.is_dir as u8;
+        buffer[at + 3..at + INODE_BYTES].copy_from_slice(&contents.blocks);
+        Ok(self.disk.write_block(block, &buffer)?)
+    }
+
+    pub fn copy_block(&mut self, from: usize, to: usize) -> Result<(), FileSystemError> {
+        let mut buffer = [0; BLOCK_SIZE];
+        self.disk.read_block(from, &mut buffer)?;
+        Ok(self.disk.write_block(to, &buffer)?)
+    }
+
+    // The name and inode in one slot of a directory, a name of all zeros
+    // if it's free. dir has to be readable, see directory.
+    pub fn entry(&self, dir: &Inode, slot: usize) -> Result<(Name, usize), FileSystemError> {
+        let mut entry = [0; ENTRY_BYTES];
+        self.read_at(dir, slot * ENTRY_BYTES, &mut entry)?;
+        let mut name = [0; MAX_FILENAME_BYTES];
+        name.copy_from_slice(&entry[..MAX_FILENAME_BYTES]);
+        Ok((name, entry[MAX_FILENAME_BYTES] as usize))
+    }
+
+    pub fn set_entry(
+        &mut self,
+        dir: usize,
+        slot: usize,
+        name: &Name,
+        inode: usize,
+    ) -> Result<(), FileSystemError> {
+        let contents = self.directory(dir)?;
+        if slot >= contents.slots() {
+            return Err(FileSystemError::FileNotFound);
+        }
+        self.write_at(&contents, slot * ENTRY_BYTES, &entry_bytes(name, inode))
+    }
+
+    // Puts name in the first free slot of dir, making one on the end if
+    // there isn't one
+    pub fn add_entry(
+        &mut self,
+        dir: usize,
+        name: &Name,
+        inode: usize,
+    ) -> Result<(), FileSystemError> {
+        let mut contents = self.directory(dir)?;
+        for slot in 0..contents.slots() {
+            if self.entry(&contents, slot)?.0[0] == 0 {
+                return self.set_entry(dir, slot, name, inode);
+            }
+        }
+        let slot = contents.slots();
+        if (slot + 1) * ENTRY_BYTES > MAX_FILE_BYTES {
+            return Err(FileSystemError::TooManyFiles);
+        }
+        self.grow(&mut contents, (slot + 1) * ENTRY_BYTES)?;
+        self.write_at(&contents, slot * ENTRY_BYTES, &entry_bytes(name, inode))?;
+        // only longer once the new entry's there to find
+        self.set_inode(dir, &contents)
+    }
+
+    // The slot and inode name has in dir, if it's there
+    pub fn lookup(
+        &self,
+        dir: usize,
+        name: &Name,
+    ) -> Result<Option<(usize, usize)>, FileSystemError> {
+        let contents = self.directory(dir)?;
+        let used = self.map(INODE_MAP)?;
+        for slot in 0..contents.slots() {
+            let (entry, inode) = self.entry(&contents, slot)?;
+            if entry == *name && inode < NUM_INODES && bit(&used, inode) {
+                return Ok(Some((slot, inode)));
+            }
+        }
+        Ok(None)
+    }
+
+    // dir's inode, as long as it's a directory that can be read
+    fn directory(&self, dir: usize) -> Result<Inode, FileSystemError> {
+        let contents = self.inode(dir)?;
+        if !contents.is_dir {
+            return Err(FileSystemError::NotADirectory);
+        }
+        if contents.blocks_in_use().is_none() {
+            return Err(FileSystemError::Damaged);
+        }
+        Ok(contents)
+    }
+
+    fn find(&self, path: &str) -> Result<usize, FileSystemError> {
+        let (dir, name) = self.parent(path)?;
+        let (_, inode) = self
+            .lookup(dir, &name)?
+            .ok_or(FileSystemError::FileNotFound)?;
+        Ok(inode)
+    }
+
+    // The directory at path, "" being the top one
+    fn find_dir(&self, path: &str) -> Result<usize, FileSystemError> {
+        let mut dir = ROOT;
+        for name in names(path) {
+            let (_, inode) = self
+                .lookup(dir, &to_name(name)?)?
+                .ok_or(FileSystemError::FileNotFound)?;
+            dir = inode;
+        }
+        self.directory(dir)?;
+        Ok(dir)
+    }
+
+    // The directory path is in, and its own name
+    fn parent(&self, path: &str) -> Result<(usize, Name), FileSystemError> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let name = to_name(name)?;
+        Ok((self.find_dir(dir)?, name))
+    }
+
+    // Whether path goes through the directory inode on the way down
+    fn is_within(&self, path: &str, inode: usize) -> Result<bool, FileSystemError> {
+        let mut dir = ROOT;
+        for name in names(path) {
+            if dir == inode {
+                return Ok(true);
+            }
+            match self.lookup(dir, &to_name(name)?)? {
+                Some((_, next)) => dir = next,
+                None => return Ok(false),
+            }
+        }
+        Ok(dir == inode)
+    }
+
+    // A new file or directory called name in dir
+    fn make(&mut self, dir: usize, name: &Name, is_dir: bool) -> Result<usize, FileSystemError> {
+        let inode = self.free_inode()?.ok_or(FileSystemError::TooManyFiles)?;
+        let contents = Inode {
+            is_dir,
+            ..Inode::EMPTY
+        };
+        self.set_inode(inode, &contents)?;
+        self.set_inode_used(inode, true)?;
+        if let Err(e) = self.add_entry(dir, name, inode) {
+            self.set_inode_used(inode, false)?;
+            return Err(e);
+        }
+        Ok(inode)
+    }
+
+    // Takes inode out of slot in dir and gives back everything it had
+    fn remove(&mut self, dir: usize, slot: usize, inode: usize) -> Result<(), FileSystemError> {
+        self.truncate(inode)?;
+        self.set_entry(dir, slot, &[0; MAX_FILENAME_BYTES], 0)?;
+        self.set_inode_used(inode, false)?;
+        Ok(self.disk.flush()?)
+    }
+
+    fn free_inode(&self) -> Result<Option<usize>, FileSystemError> {
+        let used = self.map(INODE_MAP)?;
+        Ok((1..NUM_INODES).find(|i| !bit(&used, *i)))
+    }
+
+    fn is_open(&self, inode: usize) -> bool {
+        self.open.iter().flatten().any(|open| open.inode == inode)
+    }
+
+    fn open_fd(&mut self, inode: usize, writing: bool) -> Result<usize, FileSystemError> {
+        if self.is_open(inode) {
+            return Err(FileSystemError::AlreadyOpen);
+        }
+        let fd = self
+            .open
+            .iter()
+            .position(|open| open.is_none())
+            .ok_or(FileSystemError::TooManyOpen)?;
+        self.open[fd] = Some(Open {
+            inode,
+            at: 0,
+            writing,
+        });
+        Ok(fd)
+    }
+
+    fn get_open(&self, fd: usize) -> Result<Open, FileSystemError> {
+        self.open
+            .get(fd)
+            .copied()
+            .flatten()
+            .ok_or(FileSystemError::NotOpen)
+    }
+
+    fn set_at(&mut self, fd: usize, at: usize) {
+        if let Some(Some(open)) = self.open.get_mut(fd) {
+            open.at = at;
+        }
+    }
+
+    // Gives back every block inode has. A damaged one's blocks can't be
+    // told from anyone else's, so they're left for fsck to find.
+    fn truncate(&mut self, inode: usize) -> Result<(), FileSystemError> {
+        let mut contents = self.inode(inode)?;
+        for block in contents.blocks_in_use().unwrap_or(&[]) {
+            self.set_block_used(*block as usize, false)?;
+        }
+        contents.bytes = 0;
+        self.set_inode(inode, &contents)
+    }
+
+    // Makes inode at least bytes long, with blocks for all of them.
+    // It's only changed here, writing it back is up to the caller.
+    fn grow(&mut self, inode: &mut Inode, bytes: usize) -> Result<(), FileSystemError> {
+        let needed = bytes
+            .div_ceil(BLOCK_SIZE)
+            .saturating_sub(inode.num_blocks());
+        if needed > self.free_blocks()? {
+            return Err(FileSystemError::DiskFull);
+        }
+        for i in inode.num_blocks()..bytes.div_ceil(BLOCK_SIZE) {
+            let block = self.allocate_block()?.ok_or(FileSystemError::DiskFull)?;
+            inode.blocks[i] = block as u8;
+        }
+        inode.bytes = usize::max(inode.bytes, bytes);
+        Ok(())
+    }
+
+    fn allocate_block(&mut self) -> Result<Option<usize>, FileSystemError> {
+        let map = self.map(BLOCK_MAP)?;
+        let Some(block) = (FIRST_DATA_BLOCK..NUM_BLOCKS).find(|b| !bit(&map, *b)) else {
+            return Ok(None);
+        };
+        self.set_block_used(block, true)?;
+        Ok(Some(block))
+    }
+
+    // Copies out of inode's blocks starting at byte at. The blocks
+    // have to be there already, reading never allocates.
+    fn read_at(
+        &self,
+        inode: &Inode,
+        mut at: usize,
+        mut out: &mut [u8],
+    ) -> Result<(), FileSystemError> {
+        let mut buffer = [0; BLOCK_SIZE];
+        while !out.is_empty() {
+            let offset = at % BLOCK_SIZE;
+            let n = usize::min(out.len(), BLOCK_SIZE - offset);
+            self.disk
+                .read_block(inode.blocks[at / BLOCK_SIZE] as usize, &mut buffer)?;
+            out[..n].copy_from_slice(&buffer[offset..offset + n]);
+            at += n;
+            out = &mut out[n..];
+        }
+        Ok(())
+    }
+
+    fn write_at(
+        &mut self,
+        inode: &Inode,
+        mut at: usize,
+        mut bytes: &[u8],
+    ) -> Result<(), FileSystemError> {
+        let mut buffer = [0; BLOCK_SIZE];
+        while !bytes.is_empty() {
+            let block = inode.blocks[at / BLOCK_SIZE] as usize;
+            let offset = at % BLOCK_SIZE;
+            let n = usize::min(bytes.len(), BLOCK_SIZE - offset);
+            // a whole block doesn't need what was there before
+            if n < BLOCK_SIZE {
+                self.disk.read_block(block, &mut buffer)?;
+            }
+            buffer[offset..offset + n].copy_from_slice(&bytes[..n]);
+            self.disk.write_block(block, &buffer)?;
+            at += n;
+            bytes = &bytes[n..];
+        }
+        Ok(())
+    }
+
+    fn map(&self, map: usize) -> Result<[u8; BLOCK_SIZE], FileSystemError> {
+        let mut buffer = [0; BLOCK_SIZE];
+        self.disk.read_block(map, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn set_bit(&mut self, map: usize, i: usize, on: bool) -> Result<(), FileSystemError> {
+        let mut buffer = self.map(map)?;
+        if on {
+            buffer[i / 8] |= 1 << (i % 8);
+        } else {
+            buffer[i / 8] &= !(1 << (i % 8));
+        }
+        Ok(self.disk.write_block(map, &buffer)?)
+    }
+}
+
+fn bit(map: &[u8; BLOCK_SIZE], i: usize) -> bool {
+    map[i / 8] & (1 << (i % 8)) != 0
+}
+
+fn entry_bytes(name: &Name, inode: usize) -> [u8; ENTRY_BYTES] {
+    let mut entry = [0; ENTRY_BYTES];
+    entry[..MAX_FILENAME_BYTES].copy_from_slice(name);
+    entry[MAX_FILENAME_BYTES] = inode as u8;
+    entry
+}
+
+// The names of the directories on the way down path. An empty one, from
+// a '/' at either end or two in a row, doesn't go anywhere.
+fn names(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|name| !name.is_empty())
+}
+
+// A file name as the directory keeps it, zero padded
+fn to_name(filename: &str) -> Result<Name, FileSystemError> {
+    let bytes = filename.as_bytes();
+    if bytes.is_empty()
+        || bytes.len() > MAX_FILENAME_BYTES
+        || bytes.contains(&0)
+        || bytes.contains(&b'/')
+    {
+        return Err(FileSystemError::BadFilename);
+    }
+    let mut name = [0; MAX_FILENAME_BYTES];
+    name[..bytes.len()].copy_from_slice(bytes);
+    Ok(name)
+}
+
+// The other way, up to the zeros padding it out
+pub fn filename(name: &Name) -> Result<&str, FileSystemError> {
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    core::str::from_utf8(&name[..end]).map_err(|_| FileSystemError::FileNotFound)
+}
+
+
+// Anything starting with '.', or in a directory that does, isn't shown.
+// Nothing typed in gets to make one either, so .save is never in the way.
+pub fn is_hidden(path: &str) -> bool {
+    names(path).any(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use ramdisk::RamDisk;
+
+    use super::*;
+
+    type Fs = FileSystem<RamDisk<BLOCK_SIZE, NUM_BLOCKS>>;
+
+    // what's free once the top directory has a block for its entries
+    const DATA_BLOCKS: usize = NUM_BLOCKS - FIRST_DATA_BLOCK - 1;
+
+    fn put(fs: &mut Fs, name: &str, data: &[u8]) -> Result<(), FileSystemError> {
+        let fd = fs.open_create(name)?;
+        let written = fs.write(fd, data);
+        fs.close(fd)?;
+        written
+    }
+
+    fn get<'a>(fs: &mut Fs, name: &str, buffer: &'a mut [u8]) -> &'a [u8] {
+        let fd = fs.open_read(name).unwrap();
+        let n = fs.read(fd, buffer).unwrap();
+        fs.close(fd).unwrap();
+        &buffer[..n]
+    }
+
+    #[test]
+    fn blocks_are_allocated_as_files_grow() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS + 1);
+
+        let data = [7; BLOCK_SIZE + 1];
+        put(&mut fs, "a", &data).unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS - 2);
+        let mut buffer = [0; 2 * BLOCK_SIZE];
+        assert_eq!(get(&mut fs, "a", &mut buffer), data);
+
+        // writing it again from the start gives the old blocks back first
+        put(&mut fs, "a", b"short").unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS - 1);
+        assert_eq!(get(&mut fs, "a", &mut buffer), b"short");
+    }
+
+    #[test]
+    fn writes_go_on_where_the_last_one_stopped() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        let fd = fs.open_create("a").unwrap();
+        fs.write(fd, &[1; BLOCK_SIZE - 1]).unwrap();
+        fs.write(fd, &[2; 2]).unwrap();
+        let read = fs.read(fd, &mut [0; 4]);
+        assert_eq!(read, Err(FileSystemError::NotOpenForRead));
+        fs.close(fd).unwrap();
+
+        let mut buffer = [0; 2 * BLOCK_SIZE];
+        let data = get(&mut fs, "a", &mut buffer);
+        assert_eq!(data.len(), BLOCK_SIZE + 1);
+        assert_eq!(&data[BLOCK_SIZE - 2..], [1, 2, 2]);
+    }
+
+    #[test]
+    fn names_and_open_files_are_checked() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        assert_eq!(fs.open_create(""), Err(FileSystemError::BadFilename));
+        assert_eq!(fs.open_create("elevenbytes"), Err(FileSystemError::BadFilename));
+        assert_eq!(fs.open_read("nope"), Err(FileSystemError::FileNotFound));
+        assert_eq!(fs.open_create("nope/a"), Err(FileSystemError::FileNotFound));
+        assert_eq!(fs.open_create("a/"), Err(FileSystemError::BadFilename));
+
+        let fd = fs.open_create("a").unwrap();
+        assert_eq!(fs.open_read("a"), Err(FileSystemError::AlreadyOpen));
+        fs.close(fd).unwrap();
+        assert_eq!(fs.close(fd), Err(FileSystemError::NotOpen));
+
+        for i in 0..MAX_OPEN {
+            let mut name = *b"f0";
+            name[1] += i as u8;
+            fs.open_create(core::str::from_utf8(&name).unwrap()).unwrap();
+        }
+        assert_eq!(fs.open_read("a"), Err(FileSystemError::TooManyOpen));
+    }
+
+    #[test]
+    fn running_out_of_inodes() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        for i in 0..MAX_FILES_STORED {
+            let name = [b'a' + (i / 26) as u8, b'a' + (i % 26) as u8];
+            put(&mut fs, core::str::from_utf8(&name).unwrap(), b"x").unwrap();
+        }
+        assert_eq!(put(&mut fs, "one more", b"x"), Err(FileSystemError::TooManyFiles));
+        let (count, _) = fs.list_directory("").unwrap();
+        assert_eq!(count, MAX_FILES_STORED);
+    }
+
+    #[test]
+    fn running_out_of_blocks() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        let big = [1; MAX_FILE_BYTES];
+        let mut name = *b"f0";
+        while fs.free_blocks().unwrap() >= MAX_FILE_BLOCKS {
+            put(&mut fs, core::str::from_utf8(&name).unwrap(), &big).unwrap();
+            name[1] += 1;
+        }
+        assert_eq!(put(&mut fs, "big", &big), Err(FileSystemError::DiskFull));
+
+        // a write that doesn't fit takes nothing, the file is just left empty
+        let free = fs.free_blocks().unwrap();
+        let too_much = &big[..(free + 1) * BLOCK_SIZE];
+        assert_eq!(put(&mut fs, "last", too_much), Err(FileSystemError::DiskFull));
+        assert_eq!(fs.free_blocks().unwrap(), free);
+        let mut buffer = [0; 4];
+        assert_eq!(get(&mut fs, "last", &mut buffer), b"");
+
+        // and what does fit still goes in
+        put(&mut fs, "last", &big[..free * BLOCK_SIZE]).unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), 0);
+        assert_eq!(put(&mut fs, "more", b"x"), Err(FileSystemError::DiskFull));
+    }
+
+    #[test]
+    fn file_size_comes_from_the_inode() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        put(&mut fs, "a", &[1; BLOCK_SIZE + 3]).unwrap();
+        assert_eq!(fs.file_size("a"), Ok(BLOCK_SIZE + 3));
+        put(&mut fs, "b", b"").unwrap();
+        assert_eq!(fs.file_size("b"), Ok(0));
+        assert_eq!(fs.file_size("c"), Err(FileSystemError::FileNotFound));
+    }
+
+    #[test]
+    fn too_big_for_one_file() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        let fd = fs.open_create("a").unwrap();
+        fs.write(fd, &[0; MAX_FILE_BYTES]).unwrap();
+        assert_eq!(fs.write(fd, b"x"), Err(FileSystemError::FileTooBig));
+    }
+
+    #[test]
+    fn delete_gives_the_inode_and_blocks_back() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        put(&mut fs, "a", &[1; 3 * BLOCK_SIZE]).unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS - 3);
+
+        let fd = fs.open_read("a").unwrap();
+        assert_eq!(fs.delete("a"), Err(FileSystemError::AlreadyOpen));
+        fs.close(fd).unwrap();
+        fs.delete("a").unwrap();
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS);
+        assert_eq!(fs.open_read("a"), Err(FileSystemError::FileNotFound));
+        assert_eq!(fs.delete("a"), Err(FileSystemError::FileNotFound));
+
+        // and the inode goes to the next file
+        for i in 0..MAX_FILES_STORED {
+            let name = [b'a' + (i / 26) as u8, b'a' + (i % 26) as u8];
+            put(&mut fs, core::str::from_utf8(&name).unwrap(), b"x").unwrap();
+        }
+    }
+
+    #[test]
+    fn rename_over_an_existing_file() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        put(&mut fs, "a", b"new").unwrap();
+        put(&mut fs, "b", &[2; 2 * BLOCK_SIZE]).unwrap();
+
+        fs.rename("a", "b").unwrap();
+        assert_eq!(fs.open_read("a"), Err(FileSystemError::FileNotFound));
+        let mut buffer = [0; 4];
+        assert_eq!(get(&mut fs, "b", &mut buffer), b"new");
+        assert_eq!(fs.free_blocks().unwrap(), DATA_BLOCKS - 1);
+        let (count, _) = fs.list_directory("").unwrap();
+        assert_eq!(count, 1);
+
+        assert_eq!(fs.rename("b", ""), Err(FileSystemError::BadFilename));
+        assert_eq!(fs.rename("a", "c"), Err(FileSystemError::FileNotFound));
+    }
+
+    #[test]
+    fn a_formatted_disk_mounts_as_it_was() {
+        let disk = RamDisk::new();
+        assert_eq!(is_formatted(&disk), Ok(false));
+        let mut fs = Fs::new(disk).unwrap();
+        put(&mut fs, "a", b"kept").unwrap();
+
+        let disk = fs.into_disk();
+        assert_eq!(is_formatted(&disk), Ok(true));
+        let mut fs = Fs::mount(disk);
+        let mut buffer = [0; 8];
+        assert_eq!(get(&mut fs, "a", &mut buffer), b"kept");
+
+        let small = RamDisk::<BLOCK_SIZE, 8>::new();
+        assert_eq!(is_formatted(&small), Err(DiskError::TooSmall));
+    }
+
+    #[test]
+    fn disk_errors_come_back_as_they_are() {
+        struct Broken;
+        impl BlockDevice<BLOCK_SIZE> for Broken {
+            fn num_blocks(&self) -> usize {
+                NUM_BLOCKS
+            }
+            fn read_block(&self, _: usize, _: &mut [u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+                Err(DiskError::Timeout)
+            }
+            fn write_block(&mut self, _: usize, _: &[u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+                Err(DiskError::Timeout)
+            }
+        }
+
+        let timeout = Err(FileSystemError::Disk(DiskError::Timeout));
+        assert_eq!(is_formatted(&Broken), Err(DiskError::Timeout));
+        let mut fs = FileSystem::mount(Broken);
+        assert_eq!(fs.format(), timeout);
+        assert_eq!(fs.open_create("a"), timeout.map(|_| 0));
+        assert_eq!(fs.free_blocks(), timeout.map(|_| 0));
+        assert!(!fs.exists("a"));
+    }
+
+    #[test]
+    fn directories_nest() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        fs.mkdir("lib").unwrap();
+        fs.mkdir("/lib/deep").unwrap();
+        // only each name has to fit, not the whole path
+        put(&mut fs, "lib/deep/sum1234567", b"sum").unwrap();
+        put(&mut fs, "a", b"a").unwrap();
+        let mut buffer = [0; 4];
+        assert_eq!(get(&mut fs, "lib/deep/sum1234567", &mut buffer), b"sum");
+        assert!(fs.exists("lib/deep"));
+
+        let (count, entries) = fs.list_directory("lib").unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(filename(&entries[0].name), Ok("deep"));
+        assert!(entries[0].is_dir);
+        let (count, _) = fs.list_directory("").unwrap();
+        assert_eq!(count, 2);
+
+        assert_eq!(fs.open_read("lib"), Err(FileSystemError::IsADirectory));
+        assert_eq!(fs.open_create("lib"), Err(FileSystemError::IsADirectory));
+        assert_eq!(fs.delete("lib"), Err(FileSystemError::IsADirectory));
+        assert_eq!(fs.mkdir("lib"), Err(FileSystemError::AlreadyExists));
+        assert_eq!(fs.mkdir("a/b"), Err(FileSystemError::NotADirectory));
+        assert_eq!(fs.rmdir("a"), Err(FileSystemError::NotADirectory));
+        assert_eq!(fs.rmdir("lib"), Err(FileSystemError::NotEmpty));
+        assert_eq!(fs.rmdir(""), Err(FileSystemError::BadFilename));
+
+        let free = (fs.free_blocks().unwrap(), fs.free_inodes().unwrap());
+        fs.delete("lib/deep/sum1234567").unwrap();
+        fs.rmdir("lib/deep").unwrap();
+        fs.rmdir("lib").unwrap();
+        assert!(!fs.exists("lib"));
+        // the directories' blocks come back too, the top one keeps its own
+        assert_eq!(fs.free_blocks().unwrap(), free.0 + 3);
+        assert_eq!(fs.free_inodes().unwrap(), free.1 + 3);
+    }
+
+    #[test]
+    fn directories_move_with_everything_in_them() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        fs.mkdir("lib").unwrap();
+        fs.mkdir("bin").unwrap();
+        put(&mut fs, "lib/sum", b"sum").unwrap();
+        put(&mut fs, "old", b"old").unwrap();
+
+        fs.rename("lib", "bin/lib").unwrap();
+        let mut buffer = [0; 4];
+        assert_eq!(get(&mut fs, "bin/lib/sum", &mut buffer), b"sum");
+        assert!(!fs.exists("lib"));
+        assert_eq!(fs.rename("bin", "bin/lib/bin"), Err(FileSystemError::IntoItself));
+        assert_eq!(fs.rename("bin", "bin/bin"), Err(FileSystemError::IntoItself));
+        assert_eq!(fs.rename("bin", "old"), Err(FileSystemError::AlreadyExists));
+        assert_eq!(fs.rename("old", "bin/lib"), Err(FileSystemError::AlreadyExists));
+        assert_eq!(fs.rename("old", "nope/old"), Err(FileSystemError::FileNotFound));
+
+        // a file moved over another takes its place
+        fs.rename("bin/lib/sum", "old").unwrap();
+        assert_eq!(get(&mut fs, "old", &mut buffer), b"sum");
+        let (count, _) = fs.list_directory("bin/lib").unwrap();
+        assert_eq!(count, 0);
+        fs.rename("bin/lib", "lib").unwrap();
+        let (count, _) = fs.list_directory("").unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn directories_grow_and_reuse_their_slots() {
+        let mut fs = Fs::new(RamDisk::new()).unwrap();
+        fs.mkdir("d").unwrap();
+        let count = BLOCK_SIZE / ENTRY_BYTES + 2;
+        for i in 0..count {
+            let path = [b'd', b'/', b'a' + i as u8];
+            put(&mut fs, core::str::from_utf8(&path).unwrap(), b"x").unwrap();
+        }
+        assert_eq!(fs.file_size("d"), Ok(count * ENTRY_BYTES));
+
+        fs.delete("d/a").unwrap();
+        put(&mut fs, "d/new", b"x").unwrap();
+        assert_eq!(fs.file_size("d"), Ok(count * ENTRY_BYTES));
+        let (listed, entries) = fs.list_directory("d").unwrap();
+        assert_eq!(listed, count);
+        assert_eq!(filename(&entries[0].name), Ok("new"));
+    }
+}
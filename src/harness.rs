@@ -0,0 +1,1044 @@
+// Host-side harness for driving a SwimInterface without the bootimage.
+// It owns a SwimInterface drawing into a GridSurface, so a test can feed
+// it the same DecodedKeys the keyboard interrupt would, tick it like the
+// timer would, and then compare what ended up on screen to golden text.
+//
+// Only built with the `std` feature, see the README for how to run it.
+
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::{storage::Disk, surface::GridSurface, vga::BUFFER_HEIGHT, SwimInterface};
+
+pub struct Harness {
+    swim: Box<SwimInterface<GridSurface>>,
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::with_disk(Disk::ram())
+    }
+}
+
+impl Harness {
+    // Boots like the kernel does, so handing it the disk
+    // from into_disk is as good as a reboot.
+    pub fn with_disk(disk: Disk) -> Self {
+        let mut swim = Box::new_uninit();
+        SwimInterface::boot(&mut swim, disk);
+        // boot filled it in
+        let mut swim = unsafe { swim.assume_init() };
+        swim.init();
+        swim.tick();
+        Self { swim }
+    }
+
+    // A RamDisk takes its place, nothing's going to read it
+    pub fn into_disk(mut self) -> Disk {
+        std::mem::replace(self.swim.file_system.disk_mut(), Disk::ram())
+    }
+
+    pub fn key(&mut self, key: DecodedKey) -> &mut Self {
+        self.swim.key(key);
+        self
+    }
+
+    pub fn keys(&mut self, keys: &[DecodedKey]) -> &mut Self {
+        for key in keys {
+            self.swim.key(*key);
+        }
+        self
+    }
+
+    pub fn raw(&mut self, code: KeyCode) -> &mut Self {
+        self.key(DecodedKey::RawKey(code))
+    }
+
+    // Lets go of a Shift or Ctrl pressed with raw
+    pub fn release(&mut self, code: KeyCode) -> &mut Self {
+        self.swim.key_released(code);
+        self
+    }
+
+    // Types every character of s, so "ab\n" is 'a', 'b', then Enter.
+    pub fn type_str(&mut self, s: &str) -> &mut Self {
+        for c in s.chars() {
+            self.swim.key(DecodedKey::Unicode(c));
+        }
+        self
+    }
+
+    pub fn tick(&mut self) -> &mut Self {
+        self.swim.tick();
+        self
+    }
+
+    pub fn ticks(&mut self, n: usize) -> &mut Self {
+        for _ in 0..n {
+            self.swim.tick();
+        }
+        self
+    }
+
+    pub fn swim(&mut self) -> &mut SwimInterface<GridSurface> {
+        &mut self.swim
+    }
+
+    pub fn surface(&self) -> &GridSurface {
+        self.swim.surface()
+    }
+
+    // The whole 80x25 screen, one String per row.
+    pub fn screen(&self) -> Vec<String> {
+        (0..BUFFER_HEIGHT)
+            .map(|row| self.surface().row(row).map(readable).collect())
+            .collect()
+    }
+
+    // Just the inside of one window, 0 through 3 for F1 through F4.
+    pub fn window(&self, which: usize) -> Vec<String> {
+        let w = self.swim.apps[which].window();
+        (w.y1..=w.y2)
+            .map(|row| {
+                self.surface()
+                    .span(w.x1 as usize, w.x2 as usize, row as usize)
+                    .map(readable)
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Golden text comparisons ignore trailing spaces on every row and
+    // trailing blank rows, so the expected text can be written naturally.
+    pub fn assert_screen(&self, expected: &str) {
+        assert_golden(&self.screen(), expected);
+    }
+
+    pub fn assert_window(&self, which: usize, expected: &str) {
+        assert_golden(&self.window(which), expected);
+    }
+}
+
+// The VGA buffer uses code page 437, so box drawing characters
+// show up as Latin-1 nonsense unless we translate them back.
+fn readable(c: char) -> char {
+    match c as u32 {
+        0x00 => ' ',
+        0xB3 => '│',
+        0xB4 => '┤',
+        0xBF => '┐',
+        0xC0 => '└',
+        0xC1 => '┴',
+        0xC2 => '┬',
+        0xC3 => '├',
+        0xC4 => '─',
+        0xC5 => '┼',
+        0xD9 => '┘',
+        0xDA => '┌',
+        _ => c,
+    }
+}
+
+fn trimmed(rows: &[String]) -> String {
+    let mut out: Vec<&str> = rows.iter().map(|row| row.trim_end()).collect();
+    while out.last() == Some(&"") {
+        out.pop();
+    }
+    out.join("\n")
+}
+
+fn assert_golden(rows: &[String], expected: &str) {
+    let expected: Vec<String> = expected.lines().map(String::from).collect();
+    let actual = trimmed(rows);
+    let expected = trimmed(&expected);
+    assert!(
+        actual == expected,
+        "screen did not match golden text\n--- expected\n{expected}\n--- actual\n{actual}\n"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{explorer::Explorer, window::Window};
+    use crate::storage::{self, BlockDevice, MemDisk, SECTOR_SIZE};
+    use crate::{fs::FileSystemError, BLOCK_SIZE, NUM_BLOCKS};
+    use crate::vga::{Color, ColorCode};
+
+    const ENTER: char = '\n';
+
+    #[test]
+    fn explorer_lists_seeded_files() {
+        let h = Harness::default();
+        h.assert_window(0, "hello     nums      average\npi");
+        h.assert_window(3, "hello     nums      average\npi");
+    }
+
+    #[test]
+    fn explorer_pages_when_files_outgrow_the_window() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::F5).type_str("new\n");
+
+        // Two columns by two rows can't fit five names, so each page
+        // is one row with the page indicator under it
+        let window = Window::new(1, 2, 20, 2);
+        let mut explorer = Explorer::new(window, &mut h.swim().file_system);
+        let rows = |explorer: &Explorer| {
+            let mut surface = GridSurface::default();
+            explorer.draw(&mut surface);
+            (2..4)
+                .map(|row| surface.span(1, 20, row).map(readable).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+        assert_golden(&rows(&explorer), "hello     nums\n            page 1/3");
+
+        explorer.page_down();
+        assert_golden(&rows(&explorer), "average   pi\n            page 2/3");
+
+        // the last page only draws the names there are
+        explorer.arrow_down();
+        explorer.arrow_down();
+        assert_golden(&rows(&explorer), "new\n            page 3/3");
+    }
+
+    #[test]
+    fn explorer_ctrl_arrows_go_to_the_ends_of_the_row() {
+        let mut h = Harness::default();
+        let mut explorer = Explorer::new(Window::new(1, 2, 30, 4), &mut h.swim().file_system);
+        explorer.row_end();
+        assert_eq!(explorer.name().as_str(), Ok("average"));
+        explorer.row_start();
+        assert_eq!(explorer.name().as_str(), Ok("hello"));
+
+        // the last row stops at the last name
+        explorer.arrow_down();
+        explorer.row_end();
+        assert_eq!(explorer.name().as_str(), Ok("pi"));
+    }
+
+    #[test]
+    fn explorer_makes_and_copies_files() {
+        let mut h = Harness::default();
+        h.type_str("+").tick();
+        assert!(h.screen()[1].contains("NEW FILE:"));
+        h.type_str("blank\n").tick();
+        h.assert_window(3, "hello     nums      average\npi        blank");
+
+        // a name that's taken is refused rather than emptied
+        h.type_str("cpi\n").tick();
+        assert!(h.screen()[0].contains("ERROR pi already exists"));
+
+        h.type_str("chello2\n").tick();
+        h.assert_window(2, "hello     nums      average\npi        blank     hello2");
+        h.raw(KeyCode::F2).raw(KeyCode::PageDown).type_str("e").tick();
+        h.assert_window(1, r#"print("Hello, world!")"#);
+    }
+
+    #[test]
+    fn explorer_renames_and_deletes_files() {
+        let mut h = Harness::default();
+        h.type_str("npi\n").tick();
+        assert!(h.screen()[0].contains("ERROR pi already exists"));
+        h.type_str("nhi\n").tick();
+        h.assert_window(3, "hi        nums      average\npi");
+
+        // only y deletes
+        h.type_str("d").tick();
+        assert!(h.screen()[1].contains("DELETE hi? (y/n)"));
+        h.type_str("n").tick();
+        h.assert_window(0, "hi        nums      average\npi");
+        h.type_str("dy").tick();
+        assert!(h.screen()[0].contains("Deleted hi"));
+        h.assert_window(3, "nums      average   pi");
+
+        // a directory has to be emptied first, and hi's slot is free for it
+        h.type_str("+lib/\n+lib/sum\n").tick();
+        h.assert_window(0, "lib/      nums      average\npi");
+        h.type_str("dy").tick();
+        assert!(h.screen()[0].contains("ERROR directory isn't empty"));
+        h.type_str("\ndy\x08").tick();
+        h.assert_window(0, "lib/      nums      average\npi");
+        h.type_str("dy").tick();
+        h.assert_window(0, "nums      average   pi");
+
+        // a directory is renamed along with everything in it
+        h.type_str("+lib/\n+lib/sum\nnbin\n").tick();
+        h.assert_window(0, "bin/      nums      average\npi");
+        h.type_str("\n").tick();
+        assert!(h.screen()[1].contains("/bin/:"));
+        h.assert_window(0, "sum");
+
+        // and moved into another one by giving its path from the top
+        h.type_str("\x08+tools/\n").tick();
+        h.type_str("n/tools/bin\n").tick();
+        h.assert_window(0, "nums      average   pi\ntools/");
+        h.raw(KeyCode::ArrowDown).type_str("\n\n").tick();
+        assert!(h.screen()[1].contains("/tools/bin/:"));
+        h.assert_window(0, "sum");
+
+        // but never into itself
+        h.type_str("\x08\x08n/tools/bin/x\n").tick();
+        assert!(h.screen()[0].contains("ERROR can't move a directory into itself"));
+    }
+
+    #[test]
+    fn explorer_details_view() {
+        let mut h = Harness::default();
+        h.type_str("i").tick();
+        assert_eq!(h.window(0)[0].trim_end(), "hello         22B  1/64 blocks");
+        assert_eq!(h.window(0)[9].trim_end(), "free:237 blocks,26 files");
+
+        // the footer is the whole disk's, wherever the Explorer is
+        h.type_str("+lib/\n+lib/sum\n");
+        for _ in 0..4 {
+            h.raw(KeyCode::ArrowDown);
+        }
+        h.type_str("\n").tick();
+        assert_eq!(h.window(0)[0].trim_end(), "sum            0B  0/64 blocks");
+        assert_eq!(h.window(0)[9].trim_end(), "free:236 blocks,24 files");
+
+        h.type_str("\x08i").tick();
+        h.assert_window(0, "hello     nums      average\npi        lib/");
+    }
+
+    #[test]
+    fn explorer_directories() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::F5).type_str("lib/sum\n").tick();
+        assert!(h.screen()[0].contains("ERROR"));
+        h.raw(KeyCode::F5).type_str("lib/\n").tick();
+        h.raw(KeyCode::F5).type_str("lib/sum\n").tick();
+        h.assert_window(0, "hello     nums      average\npi        lib/");
+
+        h.raw(KeyCode::ArrowDown).raw(KeyCode::ArrowRight);
+        h.type_str("\n").tick();
+        assert!(h.screen()[1].contains("/lib/:"));
+        h.assert_window(0, "sum");
+
+        // new names are made in the directory being shown
+        h.type_str("+new\n").tick();
+        h.assert_window(0, "sum       new");
+        h.assert_window(1, "hello     nums      average\npi        lib/");
+
+        // leaving an editor goes back to the file's directory
+        h.raw(KeyCode::ArrowRight).type_str("ex").raw(KeyCode::F6).type_str("s").tick();
+        assert!(h.screen()[1].contains("/lib/:"));
+        h.assert_window(0, "sum       new");
+
+        // a whole path that's taken is refused, from anywhere
+        h.key(DecodedKey::Unicode('\x08')).tick();
+        h.assert_window(0, "hello     nums      average\npi        lib/");
+        h.type_str("+lib/new\n").tick();
+        assert!(h.screen()[0].contains("ERROR lib/new already exists"));
+        h.raw(KeyCode::F5).type_str("lib/new\n").tick();
+        assert!(h.screen()[0].contains("ERROR lib/new already exists"));
+        h.type_str("\ni").tick();
+        assert_eq!(h.window(0)[1].trim_end(), "new            1B  1/64 blocks");
+
+        // only each name has to fit, not the whole path
+        h.raw(KeyCode::F5).type_str("lib/sum1234567\n").tick();
+        h.type_str("i").tick();
+        h.assert_window(0, "sum       new       sum1234567");
+        h.raw(KeyCode::F5).type_str("lib/sum12345678\n").tick();
+        assert!(h.screen()[0].contains("ERROR"));
+    }
+
+    #[test]
+    fn editor_opens_types_and_saves() {
+        let mut h = Harness::default();
+        h.type_str("e").tick();
+        h.assert_window(0, r#"print("Hello, world!")"#);
+
+        h.key(DecodedKey::Unicode(ENTER))
+            .type_str("print(2)")
+            .tick();
+        h.assert_window(0, "print(\"Hello, world!\")\nprint(2)");
+
+        // leaving asks about the edit, and s saves it, so opening
+        // it again in another window shows the edit
+        h.raw(KeyCode::F6).type_str("s");
+        h.raw(KeyCode::F2).type_str("e").tick();
+        h.assert_window(1, "print(\"Hello, world!\")\nprint(2)");
+    }
+
+    #[test]
+    fn editor_save_and_discard() {
+        let mut h = Harness::default();
+        h.type_str("e").raw(KeyCode::End).type_str("!").tick();
+        assert!(h.screen()[1].contains("EDIT:hello*,"));
+
+        // Ctrl+S saves without leaving
+        h.type_str("\x13").tick();
+        assert!(h.screen()[1].contains("EDIT:hello,"));
+        assert!(h.screen().iter().any(|row| row.contains("Saved hello")));
+
+        h.type_str("?").raw(KeyCode::F6).tick();
+        assert!(h.screen()[1].contains("(s)ave,(d)iscard,(c)ancel?"));
+        h.type_str("c").tick();
+        assert!(h.screen()[1].contains("EDIT:hello*,"));
+
+        // discarding drops the ? but keeps what Ctrl+S saved
+        h.raw(KeyCode::F6).type_str("d").tick();
+        assert!(h.screen()[1].contains("(e)dit,(r)un"));
+        h.type_str("e").tick();
+        h.assert_window(0, r#"print("Hello, world!")!"#);
+    }
+
+    #[test]
+    fn editor_save_as_and_open() {
+        let mut h = Harness::default();
+        h.type_str("e\x17abcdefghijk\n").tick();
+        assert!(h.screen()[1].contains("name too long:abcdefghijk"));
+
+        // a name that's taken is left alone
+        h.type_str("\x1B\x17pi\n").tick();
+        assert!(h.screen()[1].contains("already exists:pi"));
+
+        // the copy shows up in every explorer and the editor follows it
+        h.type_str("\x1B\x17copy\n").tick();
+        assert!(h.screen()[1].contains("EDIT:copy,"));
+        h.assert_window(3, "hello     nums      average\npi        copy");
+        // and the save didn't leave anything behind
+        assert!(!h.swim().file_system.exists(".save"));
+
+        // opening needs the changes saved first
+        h.type_str("!\x0Fnums\n").tick();
+        assert!(h.screen()[1].contains("unsaved changes:nums"));
+        h.type_str("\x1B\x13\x0Fnums\n").tick();
+        assert!(h.screen()[1].contains("EDIT:nums,"));
+        h.assert_window(0, "print(1)\nprint(257)");
+
+        h.type_str("\x0Fnope\n").tick();
+        // the error takes the place of OPEN
+        assert!(h.screen()[1].contains(":nope") && !h.screen()[1].contains("OPEN:"));
+    }
+
+    #[test]
+    fn editor_saves_in_place_when_theres_no_room_for_a_copy() {
+        let mut h = Harness::default();
+        // .save can't be made by hand
+        h.raw(KeyCode::F5).type_str(".save\n").tick();
+        assert!(h.screen().iter().any(|row| row.contains("names starting with . are hidden")));
+
+        let fs = &mut h.swim().file_system;
+        let mut name = *b"f00";
+        while fs.free_inodes().unwrap() > 0 {
+            let fd = fs.open_create(core::str::from_utf8(&name).unwrap()).unwrap();
+            fs.close(fd).unwrap();
+            name[2] += 1;
+            if name[2] > b'9' {
+                name[1] += 1;
+                name[2] = b'0';
+            }
+        }
+        h.type_str("e").raw(KeyCode::End).type_str("!\x13").tick();
+        assert!(h.screen().iter().any(|row| row.contains("Saved hello")));
+
+        // and with the disk full too, it goes where the old file was
+        let fs = &mut h.swim().file_system;
+        for name in ["f00", "f01", "f02", "f03"] {
+            let fd = fs.open_create(name).unwrap();
+            while fs.write(fd, &[0; BLOCK_SIZE]).is_ok() {}
+            fs.close(fd).unwrap();
+        }
+        assert_eq!(fs.free_blocks(), Ok(0));
+        h.type_str("?\x13").tick();
+        assert!(h.screen()[1].contains("EDIT:hello,"));
+
+        // unless it wouldn't fit, which leaves the old one alone
+        h.type_str(&"x".repeat(BLOCK_SIZE)).type_str("\x13").tick();
+        assert!(h.screen()[1].contains("disk full"));
+        h.raw(KeyCode::F6).type_str("d").type_str("e").tick();
+        h.assert_window(0, r#"print("Hello, world!")!?"#);
+    }
+
+    #[test]
+    fn editor_wraps_long_lines() {
+        let mut h = Harness::default();
+        h.type_str("e\n");
+        h.type_str(&"x".repeat(40)).tick();
+        h.assert_window(
+            0,
+            &format!(
+                "print(\"Hello, world!\")\n{}\n{}",
+                "x".repeat(33),
+                "x".repeat(7)
+            ),
+        );
+    }
+
+    #[test]
+    fn editor_wrap_modes() {
+        let mut h = Harness::default();
+        h.type_str("e\none two three four five six seven eight").tick();
+        h.assert_window(0, "print(\"Hello, world!\")\none two three four five six seven\n eight");
+
+        h.raw(KeyCode::F9).tick();
+        h.assert_window(0, "print(\"Hello, world!\")\none two three four five six\nseven eight");
+
+        // without wrapping everything scrolls sideways to keep the cursor in view
+        h.raw(KeyCode::F9).tick();
+        h.assert_window(0, "Hello, world!\")\n three four five six seven eight");
+        h.raw(KeyCode::Home).tick();
+        h.assert_window(0, "print(\"Hello, world!\")\none two three four five six seven");
+    }
+
+    #[test]
+    fn editor_undo_and_redo() {
+        let mut h = Harness::default();
+        h.type_str("e").type_str("\nprint(2)").tick();
+        h.assert_window(0, "print(\"Hello, world!\")\nprint(2)");
+
+        // the typed run is one step, and the Enter before it another
+        h.raw(KeyCode::LControl).type_str("z").release(KeyCode::LControl);
+        h.tick();
+        h.assert_window(0, "print(\"Hello, world!\")\n");
+        h.type_str("\x1A\x19\x19").tick();
+        h.assert_window(0, "print(\"Hello, world!\")\nprint(2)");
+
+        // backspace at column 0 is no longer irreversible
+        for _ in 0.."print(2)".len() {
+            h.raw(KeyCode::ArrowLeft);
+        }
+        h.type_str("\x08").tick();
+        h.assert_window(0, "print(\"Hello, world!\")print(2)");
+        h.type_str("\x1A").tick();
+        h.assert_window(0, "print(\"Hello, world!\")\nprint(2)");
+    }
+
+    #[test]
+    fn undo_never_splits_a_group() {
+        let mut h = Harness::default();
+        h.type_str("e").raw(KeyCode::LShift).raw(KeyCode::Home);
+        h.release(KeyCode::LShift).type_str("\x08").tick();
+        h.assert_window(0, "");
+
+        // a run of typing longer than the history just loses its start
+        h.type_str(&"x".repeat(300)).type_str("\x1A\x1A").tick();
+        h.assert_window(0, &format!("{}\n{}", "x".repeat(33), "x".repeat(11)));
+        h.type_str("\x19").tick();
+
+        // deleting all 300 at once is one group bigger than the history,
+        // so it can't be undone, rather than coming back in part
+        h.raw(KeyCode::LShift).raw(KeyCode::Home).release(KeyCode::LShift);
+        h.type_str("\x08").tick();
+        h.assert_window(0, "");
+        h.type_str("\x1A").tick();
+        h.assert_window(0, "");
+
+        // a group that fits is still undone whole
+        h.type_str("abc").raw(KeyCode::LShift).raw(KeyCode::Home);
+        h.release(KeyCode::LShift).type_str("\x08\x1A").tick();
+        h.assert_window(0, "abc");
+    }
+
+    #[test]
+    fn editor_holds_more_than_a_screen() {
+        let mut h = Harness::default();
+        h.type_str("e");
+        for i in 0..100 {
+            h.type_str(&format!("\nline{i}"));
+        }
+        h.tick();
+        let rows = h.window(0);
+        assert_eq!(rows.last().unwrap().trim_end(), "line99");
+
+        h.raw(KeyCode::F6).type_str("se").tick();
+        let rows = h.window(0);
+        assert_eq!(rows.last().unwrap().trim_end(), "line99");
+    }
+
+    #[test]
+    fn editor_finds_lines_past_the_line_cache() {
+        let mut h = Harness::default();
+        h.type_str("e").type_str(&"\n".repeat(2999)).type_str("end");
+        h.raw(KeyCode::F7).raw(KeyCode::F8).tick();
+        assert_eq!(h.window(0).last().unwrap().trim_end(), "3000:4  3024 bytes  modified");
+        assert!(h.window(0).iter().any(|row| row.trim_end() == "3000 end"));
+
+        h.type_str("\x072500\nY").tick();
+        assert_eq!(h.window(0).last().unwrap().trim_end(), "2500:2  3025 bytes  modified");
+        h.type_str("\x073000\n").tick();
+        assert_eq!(h.window(0).last().unwrap().trim_end(), "3000:1  3025 bytes  modified");
+    }
+
+    #[test]
+    fn editor_incremental_search() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).raw(KeyCode::ArrowRight);
+        h.type_str("e").tick();
+        assert_eq!(h.window(0)[9].trim_end(), "print((sum / count))");
+
+        h.type_str("\x06coun").tick();
+        assert!(h.screen()[1].contains("FIND:coun"));
+        assert_eq!(h.window(0)[0].trim_end(), "count := 0");
+        // the cursor sits on the first byte, the rest of the match is highlighted
+        let found = ColorCode::new(Color::Black, Color::Yellow);
+        assert!(h.surface().color_at(2, 2) == found);
+        assert!(h.surface().color_at(4, 2) == found);
+        assert!(h.surface().color_at(5, 2) != found);
+
+        h.type_str("t\n").tick();
+        assert!(h
+            .window(0)
+            .iter()
+            .any(|row| row.trim() == "count := (count + 1)"));
+
+        // Esc goes back to where we started
+        h.type_str("\x1B").tick();
+        assert!(h.screen()[1].contains("EDIT:average"));
+        assert_eq!(h.window(0)[9].trim_end(), "print((sum / count))");
+    }
+
+    #[test]
+    fn editor_highlights_syntax() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).raw(KeyCode::ArrowRight);
+        h.type_str("e");
+        h.raw(KeyCode::PageUp).raw(KeyCode::PageUp).raw(KeyCode::Home);
+        h.tick();
+        assert_eq!(h.window(0)[3].trim_end(), "while averaging {");
+        let keyword = ColorCode::new(Color::LightCyan, Color::Black);
+        assert!(h.surface().color_at(1 + 1, 2 + 3) == keyword);
+        assert!(h.surface().color_at(1 + 7, 2 + 3) != keyword);
+        // true on the line above is a literal
+        let literal = ColorCode::new(Color::Pink, Color::Black);
+        assert!(h.surface().color_at(1 + 13, 2 + 2) == literal);
+
+        // a stray closing bracket shows up once it has nothing to match
+        let unmatched = ColorCode::new(Color::White, Color::Red);
+        h.type_str(")").tick();
+        assert!(h.surface().color_at(1, 2) == unmatched);
+        h.type_str("\x08").tick();
+        assert!(h.surface().color_at(1, 2) != unmatched);
+    }
+
+    #[test]
+    fn editor_gutter_and_status_line() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).type_str("e");
+        h.raw(KeyCode::F7).raw(KeyCode::F8).tick();
+        assert_eq!(h.window(0)[0].trim_end(), " 1 print(1)");
+        assert_eq!(h.window(0)[1].trim_end(), " 2 print(257)");
+        assert_eq!(h.window(0)[9].trim_end(), "2:11  19 bytes");
+
+        // only the first row of a wrapped line gets a number
+        h.type_str(&"x".repeat(31)).tick();
+        assert_eq!(h.window(0)[2].trim_end(), "   xxxxxxxxxxx");
+        assert_eq!(h.window(0)[9].trim_end(), "2:42  50 bytes  modified");
+
+        h.raw(KeyCode::F7).raw(KeyCode::F8).tick();
+        assert_eq!(h.window(0)[0].trim_end(), "print(1)");
+        assert_eq!(h.window(0)[9].trim_end(), "");
+    }
+
+    #[test]
+    fn editor_replace_with_confirmation() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).raw(KeyCode::ArrowRight);
+        h.type_str("e").tick();
+
+        // yes to the first, no to the second, then all the rest
+        h.type_str("\x12count\ntotal\nyna").tick();
+        assert!(h.screen()[0].contains("Replaced 3 of \"count\""));
+        let text = h.window(0).join("\n");
+        assert!(text.contains("        count := (total + 1)"));
+        assert!(text.contains("print((sum / total))"));
+
+        // each replacement is undone on its own
+        h.type_str("\x1A").tick();
+        let text = h.window(0).join("\n");
+        assert!(text.contains("        count := (total + 1)"));
+        assert!(text.contains("print((sum / count))"));
+    }
+
+    #[test]
+    fn editor_auto_indent_and_brackets() {
+        let mut h = Harness::default();
+        h.type_str("e\nwhile x {\nprint(1)\n}").tick();
+        h.assert_window(0, "print(\"Hello, world!\")\nwhile x {\n    print(1)\n}");
+
+        h.type_str("\t").tick();
+        assert_eq!(h.window(0)[3].trim_end(), "    }");
+        h.raw(KeyCode::LShift).type_str("\t").release(KeyCode::LShift);
+        h.tick();
+        assert_eq!(h.window(0)[3].trim_end(), "}");
+
+        // with the cursor on the } its { lights up
+        h.raw(KeyCode::ArrowLeft).tick();
+        let matched = ColorCode::new(Color::Black, Color::Cyan);
+        assert!(h.surface().color_at(1 + 8, 2 + 1) == matched);
+        assert!(h.surface().color_at(1 + 6, 2 + 1) != matched);
+    }
+
+    #[test]
+    fn editor_go_to_line() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).raw(KeyCode::ArrowRight);
+        h.type_str("e\x074").tick();
+        assert!(h.screen()[1].contains("GO TO LINE:4"));
+        h.type_str("\nX").tick();
+        assert!(h.window(0).iter().any(|row| row.trim_end() == "Xwhile averaging {"));
+
+        // past the end is the last line
+        h.type_str("\x0799\nY").tick();
+        assert!(h.window(0).iter().any(|row| row.trim_end() == "Yprint((sum / count))"));
+    }
+
+    #[test]
+    fn editor_vi_keys() {
+        let mut h = Harness::default();
+        h.type_str("e").raw(KeyCode::F10).tick();
+        assert!(h.screen()[1].contains("-- NORMAL --"));
+
+        h.type_str("0dw").tick();
+        h.assert_window(0, r#"Hello, world!")"#);
+        h.type_str("u0x.").tick();
+        h.assert_window(0, r#"int("Hello, world!")"#);
+
+        // . repeats a whole insert, up to the Esc that ended it
+        h.type_str("A!").tick();
+        assert!(h.screen()[1].contains("-- INSERT --"));
+        h.type_str("\x1B0.").tick();
+        h.assert_window(0, r#"int("Hello, world!")!!"#);
+
+        // :q won't throw away changes, :q! will
+        h.type_str(":q\n").tick();
+        assert!(h.screen()[1].contains("VI:hello*"));
+        h.type_str(":q!\n").tick();
+        h.assert_window(0, "hello     nums      average\npi");
+    }
+
+    #[test]
+    fn editor_line_page_and_word_motion() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).raw(KeyCode::ArrowRight);
+        h.type_str("e").raw(KeyCode::Home);
+        for _ in 0..6 {
+            h.raw(KeyCode::Delete);
+        }
+        h.tick();
+        assert_eq!(h.window(0)[9].trim_end(), "(sum / count))");
+
+        // each Ctrl+Right lands on the start of the next word
+        h.raw(KeyCode::LControl).raw(KeyCode::ArrowRight);
+        h.raw(KeyCode::ArrowRight).release(KeyCode::LControl);
+        h.type_str("X").raw(KeyCode::End).type_str("!").tick();
+        assert_eq!(h.window(0)[9].trim_end(), "(sum / Xcount))!");
+
+        // up a whole window height of rows, and the long input line
+        // wraps onto two, so the top is the line just above the old top
+        h.raw(KeyCode::PageUp).tick();
+        assert_eq!(h.window(0)[0].trim_end(), "while averaging {");
+    }
+
+    #[test]
+    fn clipboard_between_windows() {
+        let mut h = Harness::default();
+        h.type_str("e");
+        // Shift held over eight Lefts selects eight letters
+        h.raw(KeyCode::LShift);
+        for _ in 0..8 {
+            h.raw(KeyCode::ArrowLeft);
+        }
+        h.release(KeyCode::LShift).tick();
+        let selected = ColorCode::new(Color::Black, Color::LightGray);
+        assert!(h.surface().color_at(1 + 15, 2) == selected);
+        assert!(h.surface().color_at(1 + 13, 2) != selected);
+
+        h.raw(KeyCode::LControl).type_str("c").release(KeyCode::LControl);
+        h.raw(KeyCode::F2).type_str("e\x16").tick();
+        h.assert_window(1, r#"print("Hello, world!")world!")"#);
+
+        // an arrow without Shift lets go of the selection, so typing
+        // after it doesn't take the selected letters with it
+        h.raw(KeyCode::F1).raw(KeyCode::LShift).raw(KeyCode::ArrowLeft);
+        h.release(KeyCode::LShift).raw(KeyCode::ArrowLeft);
+        h.type_str("X").tick();
+        h.assert_window(0, r#"print("HelloX, world!")"#);
+
+        // a running script takes the clipboard as typed input
+        h.raw(KeyCode::F4)
+            .raw(KeyCode::ArrowDown)
+            .type_str("r")
+            .ticks(50);
+        h.type_str("\x16").tick();
+        assert!(h.window(3).join("\n").contains(r#"Num terms:world!")"#));
+    }
+
+    #[test]
+    fn script_runs_and_prints() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowRight).type_str("r").ticks(50);
+        let text = h.window(0).join("\n");
+        assert!(text.contains('1'));
+        assert!(text.contains("257"));
+    }
+
+    #[test]
+    fn script_reads_input() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::ArrowDown).type_str("r").ticks(50);
+        assert!(h.window(0).join("\n").contains("Num terms:"));
+        h.type_str("1\n").ticks(200);
+        assert!(h.window(0).join("\n").contains('4'));
+    }
+
+    #[test]
+    fn script_error_goes_to_its_line() {
+        let mut h = Harness::default();
+        h.type_str("e\nprint(x)").raw(KeyCode::F6).type_str("s");
+        h.type_str("r").ticks(50);
+        assert!(h.window(0).join("\n").contains("(g) to edit line 2"));
+        h.type_str("gZ").tick();
+        assert!(h.screen()[1].contains("EDIT:hello*,"));
+        h.assert_window(0, "print(\"Hello, world!\")\nZprint(x)");
+
+        // a clean run has no line to go to, even if it prints one
+        let mut h = Harness::default();
+        h.type_str("e\nprint(\"Error on line 1: no\")").raw(KeyCode::F6).type_str("s");
+        h.type_str("r").ticks(50).type_str("g").tick();
+        h.assert_window(0, "Hello, world!\nError on line 1: no\ng");
+    }
+
+    #[test]
+    fn files_that_cant_be_read_say_why() {
+        let shown = |h: &mut Harness| h.screen().iter().any(|row| row.contains("file is damaged"));
+        let damage = |h: &mut Harness| {
+            let fs = &mut h.swim().file_system;
+            let mut hello = fs.inode(1).unwrap();
+            hello.blocks[0] = 0;
+            fs.set_inode(1, &hello).unwrap();
+        };
+
+        let mut h = Harness::default();
+        h.type_str("e\nprint(x)").raw(KeyCode::F6).type_str("s");
+        h.type_str("r").ticks(50);
+        damage(&mut h);
+        h.type_str("g").tick();
+        assert!(shown(&mut h));
+
+        let mut h = Harness::default();
+        damage(&mut h);
+        h.type_str("e").tick();
+        assert!(shown(&mut h));
+
+        let mut h = Harness::default();
+        damage(&mut h);
+        h.type_str("r").tick();
+        assert!(shown(&mut h));
+    }
+
+    #[test]
+    fn keyboard_macro_records_plays_and_saves() {
+        let mut h = Harness::default();
+        h.type_str("e").raw(KeyCode::Home).raw(KeyCode::F11);
+        h.type_str("ab").raw(KeyCode::F11);
+        h.raw(KeyCode::F12).tick();
+        assert!(h.window(0)[0].starts_with("ababprint"));
+
+        // Shift+F12 takes a number of times to play it
+        h.raw(KeyCode::LShift).raw(KeyCode::F12).release(KeyCode::LShift);
+        h.type_str("3\n").tick();
+        assert!(h.window(0)[0].starts_with("ababababab"));
+
+        // saved to a file, then loaded back over an empty macro
+        h.raw(KeyCode::LShift).raw(KeyCode::F12).release(KeyCode::LShift);
+        h.type_str("w mac\n");
+        h.raw(KeyCode::F11).raw(KeyCode::F11);
+        h.raw(KeyCode::F12).tick();
+        assert!(h.window(0)[0].starts_with("ababababab"));
+        h.raw(KeyCode::LShift).raw(KeyCode::F12).release(KeyCode::LShift);
+        h.type_str("r mac\n");
+        h.raw(KeyCode::Home).raw(KeyCode::F12).tick();
+        assert!(h.window(0)[0].starts_with("abababababab"));
+        h.assert_window(1, "hello     nums      average\npi        mac");
+    }
+
+    #[test]
+    fn files_outlast_a_reboot() {
+        // a blank disk gets the sample files written to it
+        let mut h = Harness::with_disk(Disk::Memory(MemDisk::new(NUM_BLOCKS)));
+        assert!(h.screen()[0].contains("Formatted disk"));
+
+        // and changes are on it as soon as they're made, no waiting
+        h.raw(KeyCode::F5).type_str("kept\n");
+        h.type_str("e").raw(KeyCode::End).type_str("!\x13");
+
+        let mut h = Harness::with_disk(h.into_disk());
+        assert!(h.screen()[0].contains("Mounted disk with 5 files"));
+        h.assert_window(0, "hello     nums      average\npi        kept");
+        h.type_str("e").tick();
+        h.assert_window(0, r#"print("Hello, world!")!"#);
+
+        // a disk that can't be mounted is left alone rather than formatted
+        let h = Harness::with_disk(Disk::Memory(MemDisk::new(NUM_BLOCKS - 1)));
+        assert!(h.screen()[0].contains("ERROR disk too small, disk not used"));
+        h.assert_window(0, "hello     nums      average\npi");
+    }
+
+    #[test]
+    fn saving_the_same_file_over_and_over() {
+        let mut h = Harness::with_disk(Disk::Memory(MemDisk::new(NUM_BLOCKS)));
+        h.type_str("e").raw(KeyCode::End);
+        for i in 0..40 {
+            h.type_str(&format!(" {i}\x13")).tick();
+            let title = h.screen()[1].clone();
+            assert!(title.contains("EDIT:hello,"), "save {i}: {title}");
+        }
+        h.raw(KeyCode::F6).tick();
+
+        // every save made it into the file and onto the disk
+        let mut h = Harness::with_disk(h.into_disk());
+        h.assert_window(0, "hello     nums      average\npi");
+        let fs = &mut h.swim().file_system;
+        let mut buffer = [0u8; 512];
+        let fd = fs.open_read("hello").unwrap();
+        let n = fs.read(fd, &mut buffer).unwrap();
+        fs.close(fd).unwrap();
+        let expected: String = (0..40).map(|i| format!(" {i}")).collect();
+        assert!(String::from_utf8_lossy(&buffer[..n]).contains(&expected));
+    }
+
+    #[test]
+    fn packed_images_boot_and_unpack() {
+        let files: [(&str, &[u8]); 2] = [("run", b"print(3)"), ("lib/sum", b"print((1 + 2))")];
+        let bytes = storage::pack(files).unwrap().to_bytes();
+        // the blank end of the disk is left off
+        assert!(bytes.len() < NUM_BLOCKS * SECTOR_SIZE);
+        let unpacked = storage::unpack(MemDisk::from_bytes(&bytes)).unwrap();
+        assert_eq!(unpacked[0], ("run".to_string(), b"print(3)".to_vec()));
+        assert_eq!(unpacked[1].0, "lib/sum");
+
+        let mut h = Harness::with_disk(Disk::Memory(MemDisk::from_bytes(&bytes)));
+        assert!(h.screen()[0].contains("Mounted disk with 3 files and directories"));
+        h.assert_window(0, "run       lib/");
+        h.type_str("r").ticks(50);
+        assert!(h.window(0).join("\n").contains('3'));
+
+        // names the file system can't hold are refused up front
+        let long: [(&str, &[u8]); 1] = [("much_too_long", b"")];
+        assert!(storage::pack(long).is_err());
+        // and so is anything that isn't an image
+        let blank = MemDisk::from_bytes(&[0; SECTOR_SIZE]);
+        assert_eq!(storage::unpack(blank), Err(FileSystemError::NotFormatted));
+    }
+
+    #[test]
+    fn seed_image_matches_seed_directory() {
+        let names = ["hello", "nums", "average", "pi"];
+        let files = storage::unpack(MemDisk::from_bytes(crate::SEED_IMAGE)).unwrap();
+        assert_eq!(files.len(), names.len());
+        for ((name, data), expected) in files.iter().zip(names) {
+            assert_eq!(name, expected);
+            let path = format!("{}/seed/{name}", env!("CARGO_MANIFEST_DIR"));
+            assert_eq!(
+                *data,
+                std::fs::read(path).unwrap(),
+                "rebuild seed.img, see the README"
+            );
+        }
+    }
+
+    #[test]
+    fn fsck_finds_and_fixes_corruption() {
+        let mut h = Harness::with_disk(Disk::Memory(MemDisk::new(NUM_BLOCKS)));
+        h.type_str("k").tick();
+        h.assert_window(0, "4 files, 0 dirs, 18/255 blocks\nNo problems found");
+
+        // hello's block is marked free right on the disk, in the block bitmap
+        let fs = &mut h.swim().file_system;
+        let hello = fs.inode(1).unwrap().blocks[0] as usize;
+        let Disk::Memory(disk) = fs.disk_mut() else {
+            unreachable!()
+        };
+        let mut sector = [0u8; SECTOR_SIZE];
+        disk.read_block(1, &mut sector).unwrap();
+        sector[hello / 8] &= !(1 << (hello % 8));
+        disk.write_block(1, &sector).unwrap();
+        h.type_str("k").tick();
+        h.assert_window(0, "4 files, 0 dirs, 17/255 blocks\nblocks in use marked free: 1");
+
+        h.type_str("f").tick();
+        h.assert_window(0, "After fixing:\n4 files, 0 dirs, 18/255 blocks\nNo problems found");
+
+        // and the fix is on the disk too
+        let mut h = Harness::with_disk(h.into_disk());
+        assert!(h.screen()[0].contains("Mounted disk with 4 files"));
+        h.type_str("k").tick();
+        h.assert_window(0, "4 files, 0 dirs, 18/255 blocks\nNo problems found");
+    }
+
+    #[test]
+    fn fsck_untangles_blocks() {
+        let mut h = Harness::with_disk(Disk::Memory(MemDisk::new(NUM_BLOCKS)));
+
+        // nums is pointed at hello's block, leaking its own
+        let fs = &mut h.swim().file_system;
+        let hello = fs.inode(1).unwrap();
+        let mut nums = fs.inode(2).unwrap();
+        nums.blocks[0] = hello.blocks[0];
+        fs.set_inode(2, &nums).unwrap();
+        h.type_str("k").tick();
+        h.assert_window(
+            0,
+            "4 files, 0 dirs, 18/255 blocks\nnums: shares a block with hello\n\
+             blocks used by no file: 1",
+        );
+
+        // nums gets its own copy of the block, so changing one leaves the other
+        h.type_str("f").tick();
+        h.assert_window(0, "After fixing:\n4 files, 0 dirs, 18/255 blocks\nNo problems found");
+        let fs = &mut h.swim().file_system;
+        let hello = br#"print("Hello, world!")"#;
+        let mut buffer = [0u8; 64];
+        let fd = fs.open_read("nums").unwrap();
+        let n = fs.read(fd, &mut buffer).unwrap();
+        fs.close(fd).unwrap();
+        assert_eq!(&buffer[..n], &hello[..n]);
+        let fd = fs.open_create("nums").unwrap();
+        fs.write(fd, b"print(2)").unwrap();
+        fs.close(fd).unwrap();
+        let fd = fs.open_read("hello").unwrap();
+        let n = fs.read(fd, &mut buffer).unwrap();
+        fs.close(fd).unwrap();
+        assert_eq!(&buffer[..n], hello);
+    }
+
+    #[test]
+    fn fsck_leaves_what_it_cant_get_back() {
+        let mut h = Harness::default();
+
+        // pi is too big to be real, and average's inode is freed under its name
+        let fs = &mut h.swim().file_system;
+        let mut pi = fs.inode(4).unwrap();
+        pi.bytes = 60000;
+        fs.set_inode(4, &pi).unwrap();
+        fs.set_inode_used(3, false).unwrap();
+        h.type_str("k").tick();
+        h.assert_window(
+            0,
+            "3 files, 0 dirs, 18/255 blocks\nNo disk, files are only in RAM\n\
+             average: name with no file\npi: can't be read\nblocks used by no file: 1",
+        );
+
+        // there's nothing to get pi back from, so it stays, damaged but not emptied
+        h.type_str("f").tick();
+        h.assert_window(
+            0,
+            "After fixing:\n3 files, 0 dirs, 17/255 blocks\nNo disk, files are only in RAM\n\
+             pi: can't be read",
+        );
+        let fs = &mut h.swim().file_system;
+        assert_eq!(fs.inode(4).unwrap().bytes, 60000);
+        assert_eq!(fs.open_read("pi"), Err(FileSystemError::Damaged));
+        h.raw(KeyCode::F6).tick();
+        h.assert_window(0, "hello     nums      pi");
+    }
+
+    #[test]
+    fn create_bar_adds_file_to_every_explorer() {
+        let mut h = Harness::default();
+        h.raw(KeyCode::F5).type_str("new\n").tick();
+        h.assert_window(2, "hello     nums      average\npi        new");
+    }
+}
@@ -0,0 +1,138 @@
+// pc_keyboard only hands out keys as they go down, so letting go of Shift
+// or Ctrl is spotted here instead. install() puts a stub in front of the
+// keyboard interrupt that shows each scancode to note_scancode() and then
+// carries on into pluggable_interrupt_os's own handler. The keyboard
+// controller keeps the last byte it sent until there's another, so that
+// handler still reads the same byte afterwards.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use pc_keyboard::KeyCode;
+
+const DATA: u16 = 0x60;
+// the PICs are remapped to start at 32, and the keyboard is IRQ 1
+const KEYBOARD_VECTOR: usize = 33;
+const GATE_BYTES: usize = 16;
+// interrupts are on
+const IF: u64 = 1 << 9;
+
+// scan code set 1 releases, right Ctrl is E0 9D so it ends the same as left
+const PREFIX: u8 = 0xE0;
+const LSHIFT_UP: u8 = 0xAA;
+const RSHIFT_UP: u8 = 0xB6;
+const CTRL_UP: u8 = 0x9D;
+
+// bits in RELEASED
+const LSHIFT: u8 = 1;
+const RSHIFT: u8 = 2;
+const LCTRL: u8 = 4;
+const RCTRL: u8 = 8;
+
+// modifiers let go of since take_released last looked
+static RELEASED: AtomicU8 = AtomicU8::new(0);
+// the last byte was E0
+static PREFIXED: AtomicBool = AtomicBool::new(false);
+// where the keyboard interrupt went before install
+static NEXT_HANDLER: AtomicU64 = AtomicU64::new(0);
+
+// Saves what a C call can clobber, since the interrupted code isn't expecting a call
+global_asm!(
+    ".global swim_keyboard_stub",
+    "swim_keyboard_stub:",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "cld",
+    "call {note}",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    "jmp qword ptr [rip + {next}]",
+    note = sym note_scancode,
+    next = sym NEXT_HANDLER,
+);
+
+extern "C" {
+    fn swim_keyboard_stub();
+}
+
+// Points the keyboard interrupt at the stub. Call it once, after
+// pluggable_interrupt_os has loaded its interrupt table.
+pub fn install() {
+    let mut idtr = [0u8; 10];
+    unsafe {
+        asm!("sidt [{}]", in(reg) idtr.as_mut_ptr(), options(nostack, preserves_flags));
+        let base = u64::from_le_bytes(idtr[2..].try_into().unwrap()) as *mut u8;
+        let gate = base.add(KEYBOARD_VECTOR * GATE_BYTES);
+        let flags: u64;
+        asm!("pushfq", "pop {}", out(reg) flags);
+        asm!("cli", options(nostack));
+        NEXT_HANDLER.store(handler(gate), Ordering::Relaxed);
+        set_handler(gate, swim_keyboard_stub as unsafe extern "C" fn() as usize as u64);
+        if flags & IF != 0 {
+            asm!("sti", options(nostack));
+        }
+    }
+}
+
+// Hands f each modifier let go of since the last call
+pub fn take_released(mut f: impl FnMut(KeyCode)) {
+    let released = RELEASED.swap(0, Ordering::Relaxed);
+    for (bit, key) in [
+        (LSHIFT, KeyCode::LShift),
+        (RSHIFT, KeyCode::RShift),
+        (LCTRL, KeyCode::LControl),
+        (RCTRL, KeyCode::RControl),
+    ] {
+        if released & bit != 0 {
+            f(key);
+        }
+    }
+}
+
+extern "C" fn note_scancode() {
+    let byte = unsafe { inb(DATA) };
+    let prefixed = PREFIXED.swap(byte == PREFIX, Ordering::Relaxed);
+    let released = match (prefixed, byte) {
+        (false, LSHIFT_UP) => LSHIFT,
+        (false, RSHIFT_UP) => RSHIFT,
+        (false, CTRL_UP) => LCTRL,
+        (true, CTRL_UP) => RCTRL,
+        // E0 AA and E0 B6 are the pretend shifts around some extended keys
+        _ => return,
+    };
+    RELEASED.fetch_or(released, Ordering::Relaxed);
+}
+
+// An interrupt gate keeps its handler's address in three pieces
+unsafe fn handler(gate: *mut u8) -> u64 {
+    let low = gate.cast::<u16>().read_unaligned() as u64;
+    let mid = gate.add(6).cast::<u16>().read_unaligned() as u64;
+    let high = gate.add(8).cast::<u32>().read_unaligned() as u64;
+    low | mid << 16 | high << 32
+}
+
+unsafe fn set_handler(gate: *mut u8, address: u64) {
+    gate.cast::<u16>().write_unaligned(address as u16);
+    gate.add(6).cast::<u16>().write_unaligned((address >> 16) as u16);
+    gate.add(8).cast::<u32>().write_unaligned((address >> 32) as u32);
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
@@ -1,18 +1,29 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 
 mod app;
-
-use app::{explorer::Explorer, window::Window, App};
-use file_system_solution::FileSystem;
+mod fs;
+#[cfg(feature = "std")]
+pub mod harness;
+#[cfg(not(feature = "std"))]
+pub mod keyboard;
+mod macros;
+mod scratch;
+pub mod storage;
+pub mod surface;
+mod vga;
+
+use app::{
+    check::Request, clipboard::Clipboard, explorer::Explorer, window::Window, App, SAVE_FILE,
+};
 use gc_heap::GenerationalHeap;
+use macros::{Macro, MAX_MACRO_BYTES, MAX_MACRO_PLAYS};
 use pc_keyboard::{DecodedKey, KeyCode};
-use pluggable_interrupt_os::vga_buffer::{
-    is_drawable, plot, Color, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH,
-};
-use ramdisk::RamDisk;
 use simple_interp::{ArrayString, Interpreter};
+use storage::{fsck, Disk};
+use surface::{Surface, VgaSurface};
+use vga::{is_drawable, Color, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH};
 
-use core::{fmt::Write, prelude::rust_2024::derive};
+use core::{fmt::Write, mem::MaybeUninit, prelude::rust_2024::derive, ptr::addr_of_mut};
 
 const MAX_TOKENS: usize = 100;
 const MAX_LITERAL_CHARS: usize = 15;
@@ -38,17 +49,15 @@ const NUM_BLOCKS: usize = 255;
 const MAX_FILE_BLOCKS: usize = 64;
 const MAX_FILE_BYTES: usize = MAX_FILE_BLOCKS * BLOCK_SIZE;
 const MAX_FILES_STORED: usize = 30;
-const MAX_FILENAME_BYTES: usize = 10;
-
-type FsType = FileSystem<
-    MAX_OPEN,
-    BLOCK_SIZE,
-    NUM_BLOCKS,
-    MAX_FILE_BLOCKS,
-    MAX_FILE_BYTES,
-    MAX_FILES_STORED,
-    MAX_FILENAME_BYTES,
->;
+pub const MAX_FILENAME_BYTES: usize = 10;
+// a name for each directory on the way down, joined with '/'
+pub const MAX_PATH_BYTES: usize = 32;
+
+// The files a new disk starts with, or every boot without a disk. Built
+// from seed/ with the image tool, see the README.
+static SEED_IMAGE: &[u8] = include_bytes!("../seed.img");
+
+type FsType = fs::FileSystem<Disk>;
 
 const WIN_WIDTH: usize = (WIN_REGION_WIDTH - 3) / 2;
 
@@ -62,6 +71,10 @@ const HEIGHT_DOWN: usize = (BUFFER_HEIGHT - 4) - HEIGHT_UP;
 const MIDDLE_X: usize = 1 + WIDTH_LEFT;
 const MIDDLE_Y: usize = 1 + 1 + HEIGHT_UP;
 
+// How much of a title fits between its F key and the next corner.
+// Anything longer is cut off rather than drawn over the border.
+const TITLE_WIDTH: usize = MIDDLE_X - (MIDDLE_X / 2 - 14 + 4);
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum Active {
     TopLeft = 0,
@@ -70,22 +83,22 @@ enum Active {
     BottomRight = 3,
 }
 
-fn plots(s: &str, x: usize, y: usize, limit: Option<usize>, color: ColorCode) {
+fn plots(
+    surface: &mut impl Surface,
+    s: &str,
+    x: usize,
+    y: usize,
+    limit: Option<usize>,
+    color: ColorCode,
+) {
     s.chars()
         .take(limit.unwrap_or(BUFFER_WIDTH - x))
         .enumerate()
-        .for_each(|(i, c)| plot(c, x + i, y, color));
-}
-
-fn plotu(s: &[u8], x: usize, y: usize, limit: Option<usize>, color: ColorCode) {
-    s.iter()
-        .take(limit.unwrap_or(BUFFER_WIDTH - x))
-        .enumerate()
-        .for_each(|(i, c)| plot(*c as char, x + i, y, color));
+        .for_each(|(i, c)| surface.plot(c, x + i, y, color));
 }
 
 impl Active {
-    fn draw_label(&self, titles: &[ArrayString<64>; 4], active: bool) {
+    fn draw_label(&self, surface: &mut impl Surface, titles: &[ArrayString<64>; 4], active: bool) {
         let color = ColorCode::new(
             if active {
                 Color::LightGreen
@@ -96,37 +109,55 @@ impl Active {
         );
         match self {
             Active::TopLeft => {
-                plots("F1\u{C4}\u{C4}", MIDDLE_X / 2 - 14, 1, None, color);
+                plots(surface, "F1\u{C4}\u{C4}", MIDDLE_X / 2 - 14, 1, None, color);
                 plots(
+                    surface,
                     titles[0].as_str().unwrap_or("ERR"),
                     MIDDLE_X / 2 - 14 + 4,
                     1,
-                    None,
+                    Some(TITLE_WIDTH),
                     color,
                 );
             }
             Active::TopRight => {
-                plots("F2\u{C4}\u{C4}", MIDDLE_X * 3 / 2 - 14, 1, None, color);
                 plots(
+                    surface,
+                    "F2\u{C4}\u{C4}",
+                    MIDDLE_X * 3 / 2 - 14,
+                    1,
+                    None,
+                    color,
+                );
+                plots(
+                    surface,
                     titles[1].as_str().unwrap_or("ERR"),
                     MIDDLE_X * 3 / 2 - 14 + 4,
                     1,
-                    None,
+                    Some(TITLE_WIDTH),
                     color,
                 );
             }
             Active::BottomLeft => {
-                plots("F3\u{C4}\u{C4}", MIDDLE_X / 2 - 14, MIDDLE_Y, None, color);
                 plots(
+                    surface,
+                    "F3\u{C4}\u{C4}",
+                    MIDDLE_X / 2 - 14,
+                    MIDDLE_Y,
+                    None,
+                    color,
+                );
+                plots(
+                    surface,
                     titles[2].as_str().unwrap_or("ERR"),
                     MIDDLE_X / 2 - 14 + 4,
                     MIDDLE_Y,
-                    None,
+                    Some(TITLE_WIDTH),
                     color,
                 );
             }
             Active::BottomRight => {
                 plots(
+                    surface,
                     "F4\u{C4}\u{C4}",
                     MIDDLE_X * 3 / 2 - 14,
                     MIDDLE_Y,
@@ -134,17 +165,18 @@ impl Active {
                     color,
                 );
                 plots(
+                    surface,
                     titles[3].as_str().unwrap_or("ERR"),
                     MIDDLE_X * 3 / 2 - 14 + 4,
                     MIDDLE_Y,
-                    None,
+                    Some(TITLE_WIDTH),
                     color,
                 );
             }
         }
     }
 
-    fn draw(&self, titles: &[ArrayString<64>; 4], active: bool) {
+    fn draw(&self, surface: &mut impl Surface, titles: &[ArrayString<64>; 4], active: bool) {
         let (x1, y1, x2, y2) = match self {
             Active::TopLeft => (0, 1, MIDDLE_X, MIDDLE_Y),
             Active::TopRight => (MIDDLE_X, 1, WIN_REGION_WIDTH - 2, MIDDLE_Y),
@@ -162,54 +194,60 @@ impl Active {
         );
 
         for col in x1..=x2 {
-            plot(0xC4 as char, col, y1, color);
-            plot(0xC4 as char, col, y2, color);
+            surface.plot(0xC4 as char, col, y1, color);
+            surface.plot(0xC4 as char, col, y2, color);
         }
         for row in y1..=y2 {
-            plot(0xB3 as char, x1, row, color);
-            plot(0xB3 as char, x2, row, color);
+            surface.plot(0xB3 as char, x1, row, color);
+            surface.plot(0xB3 as char, x2, row, color);
         }
 
-        self.draw_label(titles, active);
+        self.draw_label(surface, titles, active);
 
         match self {
             Active::TopLeft => {
-                plot(0xDA as char, 0, 1, color);
-                plot(0xC3 as char, 0, MIDDLE_Y, color);
-                plot(0xC2 as char, MIDDLE_X, 1, color);
-                plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
+                surface.plot(0xDA as char, 0, 1, color);
+                surface.plot(0xC3 as char, 0, MIDDLE_Y, color);
+                surface.plot(0xC2 as char, MIDDLE_X, 1, color);
+                surface.plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
 
                 // here we must redraw because we overwrote this
-                Active::BottomLeft.draw_label(titles, false);
+                Active::BottomLeft.draw_label(surface, titles, false);
             }
             Active::TopRight => {
-                plot(0xC2 as char, MIDDLE_X, 1, color);
-                plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
-                plot(0xBF as char, WIN_REGION_WIDTH - 2, 1, color);
-                plot(0xB4 as char, WIN_REGION_WIDTH - 2, MIDDLE_Y, color);
+                surface.plot(0xC2 as char, MIDDLE_X, 1, color);
+                surface.plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
+                surface.plot(0xBF as char, WIN_REGION_WIDTH - 2, 1, color);
+                surface.plot(0xB4 as char, WIN_REGION_WIDTH - 2, MIDDLE_Y, color);
 
                 // here we must redraw because we overwrote this
-                Active::BottomRight.draw_label(titles, false);
+                Active::BottomRight.draw_label(surface, titles, false);
             }
             Active::BottomLeft => {
-                plot(0xC3 as char, 0, MIDDLE_Y, color);
-                plot(0xC0 as char, 0, BUFFER_HEIGHT - 1, color);
-                plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
-                plot(0xC1 as char, MIDDLE_X, BUFFER_HEIGHT - 1, color);
+                surface.plot(0xC3 as char, 0, MIDDLE_Y, color);
+                surface.plot(0xC0 as char, 0, BUFFER_HEIGHT - 1, color);
+                surface.plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
+                surface.plot(0xC1 as char, MIDDLE_X, BUFFER_HEIGHT - 1, color);
             }
             Active::BottomRight => {
-                plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
-                plot(0xC1 as char, MIDDLE_X, BUFFER_HEIGHT - 1, color);
-                plot(0xB4 as char, WIN_REGION_WIDTH - 2, MIDDLE_Y, color);
-                plot(0xD9 as char, WIN_REGION_WIDTH - 2, BUFFER_HEIGHT - 1, color);
+                surface.plot(0xC5 as char, MIDDLE_X, MIDDLE_Y, color);
+                surface.plot(0xC1 as char, MIDDLE_X, BUFFER_HEIGHT - 1, color);
+                surface.plot(0xB4 as char, WIN_REGION_WIDTH - 2, MIDDLE_Y, color);
+                surface.plot(0xD9 as char, WIN_REGION_WIDTH - 2, BUFFER_HEIGHT - 1, color);
             }
         }
     }
 }
 
-pub struct SwimInterface {
+pub struct SwimInterface<S: Surface = VgaSurface> {
+    surface: S,
+    modifiers: Modifiers,
+    clipboard: Clipboard,
     rename_bar: RenameBar,
     editing_name: bool,
+    // Shift+F12, the rename bar is taking a macro command instead of a filename
+    macro_bar: bool,
+    keyboard_macro: Macro,
     task_manager: TaskManager,
     file_system: FsType,
     active: Active,
@@ -220,56 +258,68 @@ pub struct SwimInterface {
 
 struct TaskManager;
 
+// Which modifiers are held down. pc_keyboard only says when one goes down,
+// so letting go comes in separately through key_released.
+#[derive(Default, Clone, Copy)]
+struct Modifiers {
+    ctrl: bool,
+    shift: bool,
+}
+
 struct RenameBar {
     name: ArrayString<64>,
 }
 
 impl TaskManager {
-    fn draw(&self, ticks: &[usize; 4]) {
+    fn draw<S: Surface>(&self, surface: &mut S, ticks: &[usize; 4]) {
         let color = ColorCode::new(Color::LightGray, Color::Black);
         let color_alt = ColorCode::new(Color::LightGreen, Color::Black);
-        let plot2 = |x, y, c1, c2| {
-            plot(c1, x, y, color);
-            plot(c2, x + 1, y, color);
+        let plot2 = |surface: &mut S, x, y, c1, c2| {
+            surface.plot(c1, x, y, color);
+            surface.plot(c2, x + 1, y, color);
         };
         let mut numbuf = ArrayString::<8>::default();
-        plot2(WIN_REGION_WIDTH, 0, 'F', '1');
-        plot(0xC0 as char, WIN_REGION_WIDTH, 1, color);
+        plot2(surface, WIN_REGION_WIDTH, 0, 'F', '1');
+        surface.plot(0xC0 as char, WIN_REGION_WIDTH, 1, color);
         write!(numbuf, "{}", ticks[0]).unwrap_or(());
         plots(
+            surface,
             numbuf.as_str().unwrap_or("ERR"),
             WIN_REGION_WIDTH + 1,
             1,
             Some(4),
             color_alt,
         );
-        plot2(WIN_REGION_WIDTH, 2, 'F', '2');
-        plot(0xC0 as char, WIN_REGION_WIDTH, 3, color);
+        plot2(surface, WIN_REGION_WIDTH, 2, 'F', '2');
+        surface.plot(0xC0 as char, WIN_REGION_WIDTH, 3, color);
         numbuf.clear();
         write!(numbuf, "{}", ticks[1]).unwrap_or(());
         plots(
+            surface,
             numbuf.as_str().unwrap_or("ERR"),
             WIN_REGION_WIDTH + 1,
             3,
             Some(4),
             color_alt,
         );
-        plot2(WIN_REGION_WIDTH, 4, 'F', '3');
-        plot(0xC0 as char, WIN_REGION_WIDTH, 5, color);
+        plot2(surface, WIN_REGION_WIDTH, 4, 'F', '3');
+        surface.plot(0xC0 as char, WIN_REGION_WIDTH, 5, color);
         numbuf.clear();
         write!(numbuf, "{}", ticks[2]).unwrap_or(());
         plots(
+            surface,
             numbuf.as_str().unwrap_or("ERR"),
             WIN_REGION_WIDTH + 1,
             5,
             Some(4),
             color_alt,
         );
-        plot2(WIN_REGION_WIDTH, 6, 'F', '4');
-        plot(0xC0 as char, WIN_REGION_WIDTH, 7, color);
+        plot2(surface, WIN_REGION_WIDTH, 6, 'F', '4');
+        surface.plot(0xC0 as char, WIN_REGION_WIDTH, 7, color);
         numbuf.clear();
         write!(numbuf, "{}", ticks[3]).unwrap_or(());
         plots(
+            surface,
             numbuf.as_str().unwrap_or("ERR"),
             WIN_REGION_WIDTH + 1,
             7,
@@ -280,21 +330,38 @@ impl TaskManager {
 }
 
 impl RenameBar {
-    fn draw(&self, active: bool) {
+    fn draw(&self, surface: &mut impl Surface, label: &str, active: bool, recording: bool) {
         let color = ColorCode::new(Color::LightGray, Color::Black);
         let green = ColorCode::new(Color::LightGreen, Color::Black);
-        let label = "F5 - Filename: ";
-        plots(label, 0, 0, None, if active { green } else { color });
+        plots(
+            surface,
+            label,
+            0,
+            0,
+            None,
+            if active { green } else { color },
+        );
         let name = self.name.as_str().unwrap_or("");
-        plots(name, label.len(), 0, None, color);
+        plots(surface, name, label.len(), 0, None, color);
         for i in label.len() + name.len()..WIN_REGION_WIDTH {
-            plot(' ', i, 0, color);
+            surface.plot(' ', i, 0, color);
+        }
+        if recording {
+            let red = ColorCode::new(Color::White, Color::Red);
+            plots(surface, "REC", WIN_REGION_WIDTH - 3, 0, None, red);
         }
     }
 }
 
-impl Default for SwimInterface {
-    fn default() -> Self {
+impl<S: Surface + Default> SwimInterface<S> {
+    // Mounts the file system on disk. A blank disk is formatted with
+    // the files in SEED_IMAGE, and without a disk at all those only
+    // live in a RamDisk.
+    //
+    // A SwimInterface is a whole file system and four windows' worth of
+    // files, too big to build on the kernel stack and then move, so it's
+    // built a piece at a time in place, wherever place lives.
+    pub fn boot(place: &mut MaybeUninit<Self>, mut disk: Disk) -> &mut Self {
         let w_top_left = Window::new(1, 2, WIDTH_LEFT, HEIGHT_UP);
         let w_top_right = Window::new(1 + 1 + WIDTH_LEFT, 2, WIDTH_RIGHT, HEIGHT_UP);
         let w_bottom_left = Window::new(1, 2 + 1 + HEIGHT_UP, WIDTH_LEFT, HEIGHT_DOWN);
@@ -305,105 +372,81 @@ impl Default for SwimInterface {
             HEIGHT_DOWN,
         );
 
-        let rd = RamDisk::<BLOCK_SIZE, NUM_BLOCKS>::new();
-        let mut file_system = FileSystem::new(rd);
-
-        {
-            let fd_hello = file_system.open_create("hello").unwrap();
-            file_system
-                .write(fd_hello, r#"print("Hello, world!")"#.as_bytes())
-                .unwrap();
-            file_system.close(fd_hello).unwrap();
+        let mut status = ArrayString::default();
+        let mut seeded = None;
+        if disk.is_present() {
+            match fs::is_formatted(&disk) {
+                Ok(true) => {}
+                Ok(false) => {
+                    let _ = write!(status, "Formatted disk");
+                    seeded = Some(storage::seed(&mut disk));
+                }
+                // Don't write over something we can't read,
+                // it might still be worth getting back
+                Err(e) => {
+                    disk = Disk::ram();
+                    let _ = write!(status, "ERROR {e}, disk not used");
+                }
+            }
         }
-
-        {
-            let fd_nums = file_system.open_create("nums").unwrap();
-            file_system
-                .write(
-                    fd_nums,
-                    r#"print(1)
-print(257)"#
-                        .as_bytes(),
-                )
-                .unwrap();
-            file_system.close(fd_nums).unwrap();
+        if !disk.is_present() {
+            seeded = Some(storage::seed(&mut disk));
         }
 
-        {
-            let fd_average = file_system.open_create("average").unwrap();
-            file_system
-                .write(
-                    fd_average,
-                    r#"sum := 0
-count := 0
-averaging := true
-while averaging {
-    num := input("Enter a number:")
-    if (num == "quit") {
-        averaging := false
-    } else {
-        sum := (sum + num)
-        count := (count + 1)
-    }
-}
-print((sum / count))"#
-                        .as_bytes(),
-                )
-                .unwrap();
-            file_system.close(fd_average).unwrap();
+        let this = place.as_mut_ptr();
+        // Every field is written once before assume_init_mut
+        let file_system = unsafe {
+            let file_system = addr_of_mut!((*this).file_system);
+            file_system.write(FsType::mount(disk));
+            &mut *file_system
+        };
+        match seeded {
+            None => {
+                // the power went out partway through a save, the old file's still whole
+                let _ = file_system.delete(SAVE_FILE);
+                let files = file_system.free_inodes().map(|free| MAX_FILES_STORED - free);
+                let _ = write!(
+                    status,
+                    "Mounted disk with {} files and directories",
+                    files.unwrap_or(0)
+                );
+            }
+            Some(Ok(())) => {}
+            // an empty file system is still somewhere to put files
+            Some(Err(e)) => {
+                status.clear();
+                let _ = write!(status, "ERROR sample files: {e}");
+                if let Err(e) = file_system.format() {
+                    status.clear();
+                    let _ = write!(status, "ERROR formatting disk: {e}");
+                }
+            }
         }
 
-        {
-            let fd_pi = file_system.open_create("pi").unwrap();
-            file_system
-                .write(
-                    fd_pi,
-                    r#"sum := 0
-i := 0
-neg := false
-terms := input("Num terms:")
-while (i < terms) {
-    term := (1.0 / ((2.0 * i) + 1.0))
-    if neg {
-        term := -term
-    }
-    sum := (sum + term)
-    neg := not neg
-    i := (i + 1)
-}
-print((4 * sum))"#
-                        .as_bytes(),
-                )
-                .unwrap();
-            file_system.close(fd_pi).unwrap();
+        let windows = [w_top_left, w_top_right, w_bottom_left, w_bottom_right];
+        for (i, window) in windows.into_iter().enumerate() {
+            let explorer = App::Explorer(Explorer::new(window, file_system));
+            unsafe { addr_of_mut!((*this).apps[i]).write(explorer) };
         }
 
-        let apps = [
-            App::Explorer(Explorer::new(w_top_left, &mut file_system)),
-            App::Explorer(Explorer::new(w_top_right, &mut file_system)),
-            App::Explorer(Explorer::new(w_bottom_left, &mut file_system)),
-            App::Explorer(Explorer::new(w_bottom_right, &mut file_system)),
-        ];
-
-        let task_manager = TaskManager;
-        let rename_bar = RenameBar {
-            name: Default::default(),
-        };
-
-        Self {
-            rename_bar,
-            editing_name: false,
-            task_manager,
-            file_system,
-            active: Active::TopLeft,
-            apps,
-            ticks: [0, 0, 0, 0],
-            last_ticked: 0,
+        unsafe {
+            addr_of_mut!((*this).surface).write(S::default());
+            addr_of_mut!((*this).modifiers).write(Modifiers::default());
+            addr_of_mut!((*this).clipboard).write(Clipboard::default());
+            addr_of_mut!((*this).rename_bar).write(RenameBar { name: status });
+            addr_of_mut!((*this).editing_name).write(false);
+            addr_of_mut!((*this).macro_bar).write(false);
+            addr_of_mut!((*this).keyboard_macro).write(Macro::default());
+            addr_of_mut!((*this).task_manager).write(TaskManager);
+            addr_of_mut!((*this).active).write(Active::TopLeft);
+            addr_of_mut!((*this).ticks).write([0, 0, 0, 0]);
+            addr_of_mut!((*this).last_ticked).write(0);
+            place.assume_init_mut()
         }
     }
 }
 
-impl SwimInterface {
+impl<S: Surface> SwimInterface<S> {
     pub fn init(&mut self) {
         self.switch_active(Active::TopRight);
         self.switch_active(Active::BottomLeft);
@@ -419,16 +462,13 @@ impl SwimInterface {
         for _ in 1..4 {
             self.last_ticked += 1;
             self.last_ticked %= 4;
-            match self.apps[self.last_ticked] {
-                App::RunningScript(ref mut running_script) => {
-                    if running_script.tick() {
-                        self.ticks[self.last_ticked] += 1;
-                        // IMPORTANT: this break mades it so that only
-                        // one script ticks per overall tick
-                        break;
-                    }
+            if let App::RunningScript(ref mut running_script) = self.apps[self.last_ticked] {
+                if running_script.tick() {
+                    self.ticks[self.last_ticked] += 1;
+                    // IMPORTANT: this break mades it so that only
+                    // one script ticks per overall tick
+                    break;
                 }
-                _ => (),
             }
         }
 
@@ -458,17 +498,221 @@ impl SwimInterface {
 
     fn draw_current(&mut self) {
         for t in &mut self.apps {
-            t.draw();
+            t.draw(&mut self.surface);
             // t.window.dbgdraw()
         }
-        self.rename_bar.draw(self.editing_name);
-        self.task_manager.draw(&self.ticks);
+        let label = if self.macro_bar {
+            "F12 - Macro (times, w file, r file): "
+        } else {
+            "F5 - Filename: "
+        };
+        self.rename_bar.draw(
+            &mut self.surface,
+            label,
+            self.editing_name || self.macro_bar,
+            self.keyboard_macro.is_recording(),
+        );
+        self.task_manager.draw(&mut self.surface, &self.ticks);
+    }
+
+    pub fn surface(&self) -> &S {
+        &self.surface
     }
 
     pub fn key(&mut self, key: DecodedKey) {
+        self.record(macros::Key::Down(key));
+        let mods = self.modifiers;
+        let title = self.apps[self.active as usize].title();
         match key {
-            DecodedKey::RawKey(code) => self.handle_raw(code),
-            DecodedKey::Unicode(c) => self.handle_unicode(c),
+            DecodedKey::RawKey(KeyCode::LControl | KeyCode::RControl) => self.modifiers.ctrl = true,
+            DecodedKey::RawKey(KeyCode::LShift | KeyCode::RShift) => self.modifiers.shift = true,
+            DecodedKey::RawKey(code) => self.handle_raw(code, mods),
+            // Turn Ctrl+letter into its ASCII control code, the
+            // same thing the keyboard would send if it mapped them itself.
+            DecodedKey::Unicode(c) if mods.ctrl && c.is_ascii_alphabetic() => {
+                self.handle_unicode((c.to_ascii_uppercase() as u8 - b'@') as char, mods)
+            }
+            DecodedKey::Unicode(c) => self.handle_unicode(c, mods),
+        }
+
+        if let Some(status) = self.apps[self.active as usize].take_status() {
+            self.rename_bar.name = status;
+        }
+        if self.apps[self.active as usize].take_files_changed() {
+            self.files_changed();
+        }
+        self.run_check();
+
+        // Titles are only drawn along with the borders, so
+        // redraw them if this key changed what the title says.
+        if title.buffer_slice() != self.apps[self.active as usize].title().buffer_slice() {
+            self.switch_active(self.active);
+        }
+    }
+
+    // Shift or Ctrl let go, see keyboard.rs for how the kernel spots it
+    pub fn key_released(&mut self, key: KeyCode) {
+        let held = match key {
+            KeyCode::LControl | KeyCode::RControl => &mut self.modifiers.ctrl,
+            KeyCode::LShift | KeyCode::RShift => &mut self.modifiers.shift,
+            _ => return,
+        };
+        // both Shifts or both Ctrls share a flag, so only the first one counts
+        if *held {
+            *held = false;
+            self.record(macros::Key::Up(key));
+        }
+    }
+
+    fn record(&mut self, key: macros::Key) {
+        if self.keyboard_macro.is_recording() && !self.keyboard_macro.record(key) {
+            self.rename_bar.name.clear();
+            let _ = write!(self.rename_bar.name, "Macro full, recording stopped");
+        }
+    }
+
+    // Whenever a file might have come, gone or changed. Explorers only
+    // read the directory when told to.
+    fn files_changed(&mut self) {
+        self.refresh_explorers();
+    }
+
+    fn refresh_explorers(&mut self) {
+        for app in &mut self.apps {
+            if let App::Explorer(explorer) = app {
+                explorer.refresh(&mut self.file_system);
+            }
+        }
+    }
+
+    // The checker window can't reach the file system, so it leaves
+    // what it wants done for us to pick up
+    fn run_check(&mut self) {
+        let App::Check(check) = &mut self.apps[self.active as usize] else {
+            return;
+        };
+        match check.take_request() {
+            Some(Request::Check) => match fsck::check(&mut self.file_system) {
+                Ok(report) => check.show(report, false),
+                Err(e) => check.error(e),
+            },
+            Some(Request::Repair) => {
+                match fsck::repair(&mut self.file_system) {
+                    Ok(report) => check.show(report, true),
+                    Err(e) => check.error(e),
+                }
+                self.refresh_explorers();
+            }
+            None => {}
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.keyboard_macro.is_playing() {
+            return;
+        }
+        self.rename_bar.name.clear();
+        if self.keyboard_macro.is_recording() {
+            self.keyboard_macro.stop();
+            let _ = write!(
+                self.rename_bar.name,
+                "Recorded {} keys, F12 to play",
+                self.keyboard_macro.len()
+            );
+        } else {
+            self.keyboard_macro.start();
+            let _ = write!(self.rename_bar.name, "Recording, F11 to stop");
+        }
+    }
+
+    fn play_macro(&mut self, times: usize) {
+        if self.keyboard_macro.is_recording() || self.keyboard_macro.is_playing() {
+            return;
+        }
+        // each run starts with nothing held, and whatever
+        // is really held down comes back afterwards
+        let held = self.modifiers;
+        self.keyboard_macro.set_playing(true);
+        for _ in 0..times {
+            self.modifiers = Modifiers::default();
+            for i in 0..self.keyboard_macro.len() {
+                match self.keyboard_macro.get(i) {
+                    macros::Key::Down(key) => self.key(key),
+                    macros::Key::Up(key) => self.key_released(key),
+                }
+            }
+        }
+        self.keyboard_macro.set_playing(false);
+        self.modifiers = held;
+    }
+
+    fn open_macro_bar(&mut self) {
+        if self.keyboard_macro.is_recording() || self.keyboard_macro.is_playing() {
+            return;
+        }
+        self.macro_bar = true;
+        self.editing_name = false;
+        self.rename_bar.name.clear()
+    }
+
+    // What was typed into the Shift+F12 bar: how many times to play the
+    // macro, or w or r and a file to save it to or load it from
+    fn macro_command(&mut self) {
+        self.macro_bar = false;
+        let mut buf = [0u8; 64];
+        let command = {
+            let text = self.rename_bar.name.as_str().unwrap_or("");
+            buf[..text.len()].copy_from_slice(text.as_bytes());
+            str::from_utf8(&buf[..text.len()]).unwrap_or("").trim()
+        };
+        self.rename_bar.name.clear();
+
+        match command.split_once(' ') {
+            Some(("w", name)) if fs::is_hidden(name.trim()) => {
+                let _ = write!(self.rename_bar.name, "ERROR names starting with . are hidden");
+            }
+            Some(("w", name)) => {
+                let name = name.trim();
+                match self.keyboard_macro.save(&mut self.file_system, name) {
+                    Ok(()) => {
+                        let _ = write!(self.rename_bar.name, "Saved macro to {name}");
+                        self.files_changed();
+                    }
+                    Err(e) => {
+                        let _ = write!(self.rename_bar.name, "ERROR {e}");
+                    }
+                }
+            }
+            Some(("r", name)) => {
+                let name = name.trim();
+                let mut buffer = [0u8; MAX_MACRO_BYTES];
+                match Macro::read(&mut self.file_system, name, &mut buffer) {
+                    Ok(n) => match self.keyboard_macro.decode(&buffer[..n]) {
+                        Ok(()) => {
+                            let _ = write!(
+                                self.rename_bar.name,
+                                "Loaded {} keys from {name}",
+                                self.keyboard_macro.len()
+                            );
+                        }
+                        Err(e) => {
+                            let _ = write!(self.rename_bar.name, "ERROR {e}");
+                        }
+                    },
+                    Err(e) => {
+                        let _ = write!(self.rename_bar.name, "ERROR {e}");
+                    }
+                }
+            }
+            _ => match command.parse::<usize>() {
+                Ok(times) => self.play_macro(usize::min(times, MAX_MACRO_PLAYS)),
+                Err(_) => {
+                    let _ = write!(
+                        self.rename_bar.name,
+                        "ERROR type a number, w file or r file"
+                    );
+                }
+            },
         }
     }
 
@@ -479,12 +723,12 @@ impl SwimInterface {
             self.apps[Active::BottomLeft as usize].title(),
             self.apps[Active::BottomRight as usize].title(),
         ];
-        self.active.draw(&titles, false);
+        self.active.draw(&mut self.surface, &titles, false);
         self.active = new;
-        self.active.draw(&titles, true);
+        self.active.draw(&mut self.surface, &titles, true);
     }
 
-    fn handle_raw(&mut self, key: KeyCode) {
+    fn handle_raw(&mut self, key: KeyCode, mods: Modifiers) {
         match key {
             KeyCode::F1 => self.switch_active(Active::TopLeft),
             KeyCode::F2 => self.switch_active(Active::TopRight),
@@ -492,57 +736,109 @@ impl SwimInterface {
             KeyCode::F4 => self.switch_active(Active::BottomRight),
             KeyCode::F5 => {
                 self.editing_name = true;
+                self.macro_bar = false;
                 self.rename_bar.name.clear()
             }
             KeyCode::F6 => {
-                let (window, err) = self.apps[self.active as usize].exit(&mut self.file_system);
-                self.rename_bar.name = err;
-                self.apps[self.active as usize] =
-                    App::Explorer(Explorer::new(window, &mut self.file_system));
-                // refresh display
-                self.switch_active(self.active);
+                if let Some(explorer) = self.apps[self.active as usize].exit(&mut self.file_system) {
+                    self.rename_bar.name.clear();
+                    self.apps[self.active as usize] = explorer;
+                    // refresh display
+                    self.switch_active(self.active);
+                }
+            }
+            KeyCode::F7 => self.apps[self.active as usize].toggle_gutter(),
+            KeyCode::F8 => self.apps[self.active as usize].toggle_status_line(),
+            KeyCode::F9 => self.apps[self.active as usize].cycle_wrap(),
+            KeyCode::F10 => self.apps[self.active as usize].toggle_vi(),
+            KeyCode::F11 => self.toggle_recording(),
+            KeyCode::F12 if mods.shift => self.open_macro_bar(),
+            KeyCode::F12 => self.play_macro(1),
+            KeyCode::ArrowLeft if mods.ctrl => {
+                self.apps[self.active as usize].word_left(mods.shift)
+            }
+            KeyCode::ArrowRight if mods.ctrl => {
+                self.apps[self.active as usize].word_right(mods.shift)
+            }
+            KeyCode::ArrowLeft => self.apps[self.active as usize].arrow_left(mods.shift),
+            KeyCode::ArrowRight => self.apps[self.active as usize].arrow_right(mods.shift),
+            KeyCode::ArrowUp => self.apps[self.active as usize].arrow_up(mods.shift),
+            KeyCode::ArrowDown => self.apps[self.active as usize].arrow_down(mods.shift),
+            KeyCode::Home => self.apps[self.active as usize].home(mods.shift),
+            KeyCode::End => self.apps[self.active as usize].end(mods.shift),
+            KeyCode::PageUp => self.apps[self.active as usize].page_up(mods.shift),
+            KeyCode::PageDown => self.apps[self.active as usize].page_down(mods.shift),
+            KeyCode::Delete => {
+                self.apps[self.active as usize].delete(&mut self.file_system);
             }
-            KeyCode::ArrowLeft => self.apps[self.active as usize].arrow_left(),
-            KeyCode::ArrowRight => self.apps[self.active as usize].arrow_right(),
-            KeyCode::ArrowUp => self.apps[self.active as usize].arrow_up(),
-            KeyCode::ArrowDown => self.apps[self.active as usize].arrow_down(),
             _ => {}
         }
     }
 
-    fn handle_unicode(&mut self, key: char) {
+    fn handle_unicode(&mut self, key: char, mods: Modifiers) {
         const ASCII_ENTER: char = '\n';
         const ASCII_DEL: char = '\x7F';
         const ASCII_BS: char = '\x08';
-
-        if self.editing_name {
+        const ASCII_ESC: char = '\x1B';
+        const ASCII_TAB: char = '\t';
+        const ASCII_CTRL_C: char = '\x03';
+        const ASCII_CTRL_F: char = '\x06';
+        const ASCII_CTRL_G: char = '\x07';
+        const ASCII_CTRL_R: char = '\x12';
+        const ASCII_CTRL_O: char = '\x0F';
+        const ASCII_CTRL_S: char = '\x13';
+        const ASCII_CTRL_V: char = '\x16';
+        const ASCII_CTRL_W: char = '\x17';
+        const ASCII_CTRL_X: char = '\x18';
+        const ASCII_CTRL_Y: char = '\x19';
+        const ASCII_CTRL_Z: char = '\x1A';
+
+        if self.macro_bar {
+            match key {
+                ASCII_ENTER => self.macro_command(),
+                ASCII_ESC => {
+                    self.macro_bar = false;
+                    self.rename_bar.name.clear();
+                }
+                k => self.rename_bar.name.push_char(k),
+            }
+        } else if self.editing_name {
             match key {
                 ASCII_ENTER => {
                     self.editing_name = false;
 
                     match self.rename_bar.name.as_str() {
                         Ok(name) => {
-                            if name.len() < 1 {
+                            if name.is_empty() {
                                 self.rename_bar.name.clear();
                                 let _ = write!(
                                     self.rename_bar.name,
                                     "ERROR File name must be at least one character"
                                 );
+                            } else if fs::is_hidden(name) {
+                                self.rename_bar.name.clear();
+                                let _ = write!(
+                                    self.rename_bar.name,
+                                    "ERROR names starting with . are hidden"
+                                );
+                            } else if self.file_system.exists(name.trim_end_matches('/')) {
+                                // open_create would quietly empty it
+                                let mut error = ArrayString::<64>::default();
+                                let _ = write!(error, "ERROR {name} already exists");
+                                self.rename_bar.name = error;
                             } else {
-                                match self.file_system.open_create(name) {
-                                    Ok(fd) => match self.file_system.close(fd) {
-                                        Ok(()) => {
-                                            self.rename_bar.name.clear();
-                                        }
-                                        Err(e) => {
-                                            self.rename_bar.name.clear();
-                                            let _ = write!(self.rename_bar.name, "ERROR {e}");
-                                        }
-                                    },
-                                    Err(e) => {
-                                        self.rename_bar.name.clear();
-                                        let _ = write!(self.rename_bar.name, "ERROR {e}");
-                                    }
+                                // a path from the top, and one
+                                // ending in '/' is a new directory
+                                let made = match name.strip_suffix('/') {
+                                    Some(dir) => self.file_system.mkdir(dir),
+                                    None => self
+                                        .file_system
+                                        .open_create(name)
+                                        .and_then(|fd| self.file_system.close(fd)),
+                                };
+                                self.rename_bar.name.clear();
+                                if let Err(e) = made {
+                                    let _ = write!(self.rename_bar.name, "ERROR {e}");
                                 }
                             }
                         }
@@ -552,24 +848,75 @@ impl SwimInterface {
                         }
                     }
 
-                    for i in 0..4 {
-                        if let App::Explorer(ref exp) = self.apps[i] {
-                            self.apps[i] = App::Explorer(Explorer::new(
-                                exp.window.clone(),
-                                &mut self.file_system,
-                            ));
-                        }
-                    }
+                    self.files_changed();
                 }
                 k => self.rename_bar.name.push_char(k),
             }
         } else {
             if let Some(newapp) = match key {
-                ASCII_ENTER => self.apps[self.active as usize].newline(),
-                ASCII_BS | ASCII_DEL => self.apps[self.active as usize].backspace(),
-                k if is_drawable(k) => {
-                    self.apps[self.active as usize].insert_char(key, &mut self.file_system)
+                ASCII_ENTER if mods.shift => {
+                    self.apps[self.active as usize].shift_newline(&mut self.file_system)
+                }
+                ASCII_ENTER => self.apps[self.active as usize].newline(&mut self.file_system),
+                ASCII_BS => self.apps[self.active as usize].backspace(&mut self.file_system),
+                ASCII_DEL => self.apps[self.active as usize].delete(&mut self.file_system),
+                ASCII_ESC => {
+                    self.apps[self.active as usize].escape();
+                    None
+                }
+                ASCII_TAB => {
+                    self.apps[self.active as usize].indent(mods.shift);
+                    None
+                }
+                ASCII_CTRL_F => {
+                    self.apps[self.active as usize].find();
+                    None
+                }
+                ASCII_CTRL_G => {
+                    self.apps[self.active as usize].go_to();
+                    None
+                }
+                ASCII_CTRL_R => {
+                    self.apps[self.active as usize].replace();
+                    None
+                }
+                ASCII_CTRL_S => {
+                    self.apps[self.active as usize].save(&mut self.file_system);
+                    None
+                }
+                ASCII_CTRL_W => {
+                    self.apps[self.active as usize].save_as();
+                    None
+                }
+                ASCII_CTRL_O => {
+                    self.apps[self.active as usize].open();
+                    None
+                }
+                ASCII_CTRL_C => {
+                    self.apps[self.active as usize].copy(&mut self.clipboard);
+                    None
+                }
+                ASCII_CTRL_X => {
+                    self.apps[self.active as usize].cut(&mut self.clipboard);
+                    None
+                }
+                ASCII_CTRL_V => {
+                    self.apps[self.active as usize].paste(&self.clipboard);
+                    None
+                }
+                ASCII_CTRL_Z => {
+                    self.apps[self.active as usize].undo();
+                    None
+                }
+                ASCII_CTRL_Y => {
+                    self.apps[self.active as usize].redo();
+                    None
                 }
+                k if is_drawable(k) => self.apps[self.active as usize].insert_char(
+                    key,
+                    &mut self.file_system,
+                    &mut self.clipboard,
+                ),
                 _ => None,
             } {
                 self.apps[self.active as usize] = newapp;
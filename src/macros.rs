@@ -0,0 +1,210 @@
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::{fs::FileSystemError, FsType};
+
+pub const MAX_MACRO_KEYS: usize = 256;
+// so a typo in the play count can't hang the whole interface
+pub const MAX_MACRO_PLAYS: usize = 1000;
+
+// Saved macros are text, with each raw key written as a '\0' and then a
+// letter saying which key it was, capital for pressing it and lower case
+// for letting go. '\0' is never typed, so it can't be mistaken for a real
+// key. Raw keys not in here do nothing anyway and aren't recorded.
+const RAW_KEYS: [KeyCode; 23] = [
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Delete,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+];
+
+// The longest a saved macro can be, every key as a 4 byte char
+pub const MAX_MACRO_BYTES: usize = MAX_MACRO_KEYS * 4;
+
+#[derive(Clone, Copy)]
+pub enum Key {
+    Down(DecodedKey),
+    // only Shift and Ctrl are ever seen coming back up
+    Up(KeyCode),
+}
+
+// One keyboard macro for the whole interface. F11 starts and stops
+// recording, F12 plays it back, and every key in between goes through
+// SwimInterface::key again just like it was typed, so it works in any app.
+pub struct Macro {
+    keys: [Key; MAX_MACRO_KEYS],
+    len: usize,
+    recording: bool,
+    // set while playing, so F11 and F12 can't be
+    // used again until the macro is done
+    playing: bool,
+}
+
+impl Default for Macro {
+    fn default() -> Self {
+        Self {
+            keys: [Key::Down(DecodedKey::Unicode('\0')); MAX_MACRO_KEYS],
+            len: 0,
+            recording: false,
+            playing: false,
+        }
+    }
+}
+
+impl Macro {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, i: usize) -> Key {
+        self.keys[i]
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    pub fn start(&mut self) {
+        self.len = 0;
+        self.recording = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = false;
+        // a Shift or Ctrl right before F11 would otherwise
+        // land on whatever comes after the macro
+        while self.len > 0 && is_modifier(self.keys[self.len - 1]) {
+            self.len -= 1;
+        }
+    }
+
+    // false once the macro is full, which also stops the recording
+    pub fn record(&mut self, key: Key) -> bool {
+        if let Key::Down(DecodedKey::RawKey(code)) | Key::Up(code) = key {
+            if !RAW_KEYS.contains(&code) {
+                return true;
+            }
+        }
+        if self.len == MAX_MACRO_KEYS {
+            self.stop();
+            return false;
+        }
+        self.keys[self.len] = key;
+        self.len += 1;
+        true
+    }
+
+    pub fn encode(&self, out: &mut [u8; MAX_MACRO_BYTES]) -> usize {
+        let mut n = 0;
+        for key in &self.keys[..self.len] {
+            let (code, first) = match key {
+                Key::Down(DecodedKey::Unicode(c)) => {
+                    n += c.encode_utf8(&mut out[n..]).len();
+                    continue;
+                }
+                Key::Down(DecodedKey::RawKey(code)) => (code, b'A'),
+                Key::Up(code) => (code, b'a'),
+            };
+            let i = RAW_KEYS.iter().position(|k| k == code).unwrap_or(0);
+            out[n] = 0;
+            out[n + 1] = first + i as u8;
+            n += 2;
+        }
+        n
+    }
+
+    // Replaces the macro with a decoded one. If the bytes
+    // aren't a macro the old one is gone all the same.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.len = 0;
+        let text = str::from_utf8(bytes).map_err(|_| "not a macro")?;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            let key = if c == '\0' {
+                let key = chars.next().and_then(|c| {
+                    let up = c.is_ascii_lowercase();
+                    let first = if up { 'a' } else { 'A' };
+                    let code = *RAW_KEYS.get((c as usize).checked_sub(first as usize)?)?;
+                    Some(if up {
+                        Key::Up(code)
+                    } else {
+                        Key::Down(DecodedKey::RawKey(code))
+                    })
+                });
+                match key {
+                    Some(key) => key,
+                    None => {
+                        self.len = 0;
+                        return Err("not a macro");
+                    }
+                }
+            } else {
+                Key::Down(DecodedKey::Unicode(c))
+            };
+            if self.len == MAX_MACRO_KEYS {
+                self.len = 0;
+                return Err("macro too long");
+            }
+            self.keys[self.len] = key;
+            self.len += 1;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, fs: &mut FsType, filename: &str) -> Result<(), FileSystemError> {
+        let mut buffer = [0u8; MAX_MACRO_BYTES];
+        let n = self.encode(&mut buffer);
+        let fd = fs.open_create(filename)?;
+        // close it even if the write fails, or it's open for good
+        let written = fs.write(fd, &buffer[..n]);
+        fs.close(fd)?;
+        written
+    }
+
+    // Reads a macro file into buffer, for decode to pick up
+    pub fn read(
+        fs: &mut FsType,
+        filename: &str,
+        buffer: &mut [u8; MAX_MACRO_BYTES],
+    ) -> Result<usize, FileSystemError> {
+        let fd = fs.open_read(filename)?;
+        let n = fs.read(fd, buffer);
+        fs.close(fd)?;
+        n
+    }
+}
+
+fn is_modifier(key: Key) -> bool {
+    matches!(
+        key,
+        Key::Down(DecodedKey::RawKey(
+            KeyCode::LShift | KeyCode::RShift | KeyCode::LControl | KeyCode::RControl
+        ))
+    )
+}
@@ -1,10 +1,12 @@
 #![no_std]
 #![no_main]
 
+use core::{mem::MaybeUninit, ptr::addr_of_mut};
+
 use crossbeam::atomic::AtomicCell;
 use pc_keyboard::DecodedKey;
 use pluggable_interrupt_os::{vga_buffer::clear_screen, HandlerTable};
-use simple_swim_template::SwimInterface;
+use simple_swim_template::{keyboard, storage::Disk, SwimInterface};
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -20,13 +22,18 @@ static LAST_KEY: AtomicCell<Option<DecodedKey>> = AtomicCell::new(None);
 static TICKED: AtomicCell<bool> = AtomicCell::new(false);
 
 fn cpu_loop() -> ! {
-    let mut kernel = SwimInterface::default();
+    // far too big for the stack, so it's built where it stays
+    static mut KERNEL: MaybeUninit<SwimInterface> = MaybeUninit::uninit();
+    let kernel = SwimInterface::boot(unsafe { &mut *addr_of_mut!(KERNEL) }, Disk::probe());
     kernel.init();
+    keyboard::install();
     loop {
         if let Ok(_) = TICKED.compare_exchange(true, false) {
             kernel.tick();
         }
 
+        keyboard::take_released(|k| kernel.key_released(k));
+
         if let Ok(k) = LAST_KEY.fetch_update(|k| if k.is_some() { Some(None) } else { None }) {
             if let Some(k) = k {
                 kernel.key(k);
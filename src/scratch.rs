@@ -0,0 +1,38 @@
+// One file's worth of bytes, lent to whatever needs a whole file in memory
+// for a moment: saving, loading, copying, running and checking files. The
+// kernel stack has no room for each of those to keep its own, and only one
+// of them happens at a time, so they share this. Asking for it again before
+// it's been given back is a bug, so that panics instead of handing out the
+// same bytes twice.
+
+use crate::MAX_FILE_BYTES;
+
+#[cfg(not(feature = "std"))]
+pub fn with_file_buffer<R>(f: impl FnOnce(&mut [u8; MAX_FILE_BYTES]) -> R) -> R {
+    use core::{
+        ptr::addr_of_mut,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static LENT: AtomicBool = AtomicBool::new(false);
+    static mut BUFFER: [u8; MAX_FILE_BYTES] = [0; MAX_FILE_BYTES];
+
+    assert!(!LENT.swap(true, Ordering::Acquire), "file buffer already lent");
+    // LENT says nobody else has it
+    let result = f(unsafe { &mut *addr_of_mut!(BUFFER) });
+    LENT.store(false, Ordering::Release);
+    result
+}
+
+// The harness runs its tests on several threads, so each one gets its own
+#[cfg(feature = "std")]
+pub fn with_file_buffer<R>(f: impl FnOnce(&mut [u8; MAX_FILE_BYTES]) -> R) -> R {
+    use std::{boxed::Box, cell::RefCell, thread_local};
+
+    thread_local! {
+        static BUFFER: RefCell<Box<[u8; MAX_FILE_BYTES]>> =
+            RefCell::new(Box::new([0; MAX_FILE_BYTES]));
+    }
+
+    BUFFER.with(|buffer| f(&mut buffer.borrow_mut()))
+}
@@ -0,0 +1,193 @@
+// A PIO driver for plain ATA drives, which is what QEMU's -drive gives us.
+// Every sector goes through the data port one word at a time, which is
+// slow but needs no DMA or interrupts. Sectors are addressed with 28 bit
+// LBA, far more than an image will ever need.
+
+use core::arch::asm;
+
+use super::{BlockDevice, DiskError, SECTOR_SIZE};
+
+// the harness never probes, so these are only for the kernel
+#[cfg(not(feature = "std"))]
+const PRIMARY: u16 = 0x1F0;
+#[cfg(not(feature = "std"))]
+const SECONDARY: u16 = 0x170;
+
+// port offsets from a bus's base
+const DATA: u16 = 0;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DRIVE: u16 = 6;
+// status when read, command when written
+const STATUS: u16 = 7;
+const COMMAND: u16 = 7;
+
+const READ_SECTORS: u8 = 0x20;
+const WRITE_SECTORS: u8 = 0x30;
+const CACHE_FLUSH: u8 = 0xE7;
+#[cfg(not(feature = "std"))]
+const IDENTIFY: u8 = 0xEC;
+
+const BSY: u8 = 0x80;
+const DF: u8 = 0x20;
+const DRQ: u8 = 0x08;
+const ERR: u8 = 0x01;
+
+// how many times to poll the status before giving up on the drive
+const TIMEOUT: usize = 1_000_000;
+
+pub struct Ata {
+    base: u16,
+    slave: bool,
+    sectors: usize,
+}
+
+impl Ata {
+    // The bootimage is the primary master, so files go on the next drive
+    // along. That's -drive index=1 for QEMU, or any of the later ones.
+    #[cfg(not(feature = "std"))]
+    pub fn probe() -> Option<Self> {
+        [(PRIMARY, true), (SECONDARY, false), (SECONDARY, true)]
+            .into_iter()
+            .find_map(|(base, slave)| Self::identify(base, slave))
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn identify(base: u16, slave: bool) -> Option<Self> {
+        let mut ata = Self {
+            base,
+            slave,
+            sectors: 0,
+        };
+        unsafe {
+            // nothing answers on a bus with no controller
+            if inb(base + STATUS) == 0xFF {
+                return None;
+            }
+            outb(base + DRIVE, if slave { 0xB0 } else { 0xA0 });
+            ata.settle();
+            outb(base + SECTOR_COUNT, 0);
+            outb(base + LBA_LOW, 0);
+            outb(base + LBA_MID, 0);
+            outb(base + LBA_HIGH, 0);
+            outb(base + COMMAND, IDENTIFY);
+            if inb(base + STATUS) == 0 {
+                return None;
+            }
+            // CD drives and SATA ones leave a signature here instead
+            ata.wait(false).ok()?;
+            if inb(base + LBA_MID) != 0 || inb(base + LBA_HIGH) != 0 {
+                return None;
+            }
+            ata.wait(true).ok()?;
+            let mut words = [0u16; SECTOR_SIZE / 2];
+            for word in &mut words {
+                *word = inw(base + DATA);
+            }
+            // words 60 and 61 are how many sectors LBA28 can reach
+            ata.sectors = words[60] as usize | ((words[61] as usize) << 16);
+        }
+        (ata.sectors > 0).then_some(ata)
+    }
+
+    // The drive takes a moment to switch over after being selected,
+    // and reading the status a few times is the usual way to wait it out.
+    fn settle(&self) {
+        for _ in 0..4 {
+            unsafe { inb(self.base + STATUS) };
+        }
+    }
+
+    // Waits for the drive to stop being busy, and then for it to
+    // have data to move too if drq is set
+    fn wait(&self, drq: bool) -> Result<(), DiskError> {
+        for _ in 0..TIMEOUT {
+            let status = unsafe { inb(self.base + STATUS) };
+            if status & BSY != 0 {
+                continue;
+            }
+            if status & (ERR | DF) != 0 {
+                return Err(DiskError::Device);
+            }
+            if !drq || status & DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(DiskError::Timeout)
+    }
+
+    fn select(&self, lba_top: u8) {
+        let drive = 0xE0 | ((self.slave as u8) << 4) | (lba_top & 0x0F);
+        unsafe { outb(self.base + DRIVE, drive) };
+        self.settle();
+    }
+
+    // Sets up a one sector transfer and waits until it can start
+    fn command(&self, block: usize, command: u8) -> Result<(), DiskError> {
+        if block >= self.sectors {
+            return Err(DiskError::OutOfRange);
+        }
+        self.wait(false)?;
+        self.select((block >> 24) as u8);
+        unsafe {
+            outb(self.base + SECTOR_COUNT, 1);
+            outb(self.base + LBA_LOW, block as u8);
+            outb(self.base + LBA_MID, (block >> 8) as u8);
+            outb(self.base + LBA_HIGH, (block >> 16) as u8);
+            outb(self.base + COMMAND, command);
+        }
+        self.wait(true)
+    }
+}
+
+impl BlockDevice for Ata {
+    fn num_blocks(&self) -> usize {
+        self.sectors
+    }
+
+    fn read_block(&self, block: usize, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        self.command(block, READ_SECTORS)?;
+        for pair in buffer.chunks_exact_mut(2) {
+            let word = unsafe { inw(self.base + DATA) };
+            pair.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: usize, buffer: &[u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        self.command(block, WRITE_SECTORS)?;
+        for pair in buffer.chunks_exact(2) {
+            unsafe { outw(self.base + DATA, u16::from_le_bytes([pair[0], pair[1]])) };
+        }
+        self.wait(false)
+    }
+
+    fn flush(&mut self) -> Result<(), DiskError> {
+        self.wait(false)?;
+        self.select(0);
+        unsafe { outb(self.base + COMMAND, CACHE_FLUSH) };
+        self.wait(false)
+    }
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+unsafe fn inw(port: u16) -> u16 {
+    let value: u16;
+    asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+unsafe fn outw(port: u16, value: u16) {
+    asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+}
@@ -0,0 +1,549 @@
+// A consistency check over the file system's own bookkeeping (see
+// src/fs.rs). It walks the directories down from the top to see which
+// inodes are really whose, and every inode in use to see which blocks
+// are, then holds that up against the bitmaps:
+//
+// - a name for an inode that isn't in use, or an inode in use that no
+//   directory has, a name that isn't text, or a name used twice in one
+//   directory
+// - an inode two names lead to, which for a directory would go round
+//   in circles
+// - an inode whose size or blocks are off the disk can't be read at all
+// - a block the bitmap has in use that no file has is leaked
+// - a block a file has that the bitmap has free is about to be handed out
+// - a block two files have means writing one changes the other
+
+use core::fmt::{self, Write};
+
+use simple_interp::ArrayString;
+
+use super::BlockDevice;
+use crate::{
+    fs::{filename, FileSystemError, Inode, Name, FIRST_DATA_BLOCK, NUM_INODES, ROOT},
+    FsType, MAX_FILENAME_BYTES, NUM_BLOCKS,
+};
+
+pub const MAX_PROBLEMS: usize = 16;
+
+#[derive(Clone, Copy)]
+pub enum Problem {
+    // nothing it names can be trusted, so nothing else gets checked
+    BadDirectory,
+    // size or blocks off the disk, see Inode::blocks_in_use
+    Unreadable(Name),
+    // the second file has a block the first does too, None is the top directory
+    Shared(Name, Option<Name>),
+    Leaked(usize),
+    Unmarked(usize),
+    // a name whose inode isn't in use
+    Dangling(Name),
+    // a name for an inode that's already got one
+    Linked(Name),
+    // an inode in use, by number, that no directory has
+    Unnamed(usize),
+    // an inode in use whose name isn't text
+    BadName(usize),
+    Duplicate(Name),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::BadDirectory => write!(f, "top directory is damaged"),
+            Problem::Unreadable(name) => write!(f, "{}: can't be read", shown(name)),
+            Problem::Shared(name, Some(other)) => {
+                write!(f, "{}: shares a block with {}", shown(name), shown(other))
+            }
+            Problem::Shared(name, None) => {
+                write!(f, "{}: shares a block with the top directory", shown(name))
+            }
+            Problem::Leaked(blocks) => write!(f, "blocks used by no file: {blocks}"),
+            Problem::Unmarked(blocks) => write!(f, "blocks in use marked free: {blocks}"),
+            Problem::Dangling(name) => write!(f, "{}: name with no file", shown(name)),
+            Problem::Linked(name) => write!(f, "{}: already somewhere else", shown(name)),
+            Problem::Unnamed(inode) => write!(f, "inode {inode}: file with no name"),
+            Problem::BadName(inode) => write!(f, "inode {inode}: name isn't text"),
+            Problem::Duplicate(name) => write!(f, "{}: listed twice", shown(name)),
+        }
+    }
+}
+
+fn shown(name: &Name) -> &str {
+    match filename(name) {
+        Ok("") | Err(_) => "?",
+        Ok(name) => name,
+    }
+}
+
+fn to_name(bytes: &[u8]) -> Name {
+    let mut name = [0; MAX_FILENAME_BYTES];
+    let n = usize::min(bytes.len(), MAX_FILENAME_BYTES);
+    name[..n].copy_from_slice(&bytes[..n]);
+    name
+}
+
+// Text without a '/', and nothing after the zeros that pad it out
+fn is_text(name: &Name) -> bool {
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    filename(name).is_ok_and(|name| !name.contains('/')) && name[end..].iter().all(|b| *b == 0)
+}
+
+// What fsck calls an inode it had to give a name
+fn lost(inode: usize) -> Name {
+    let mut lost = ArrayString::<MAX_FILENAME_BYTES>::default();
+    let _ = write!(lost, "lost{inode}");
+    to_name(lost.buffer_slice())
+}
+
+pub struct Report {
+    pub files: usize,
+    // not counting the top one
+    pub dirs: usize,
+    pub blocks: usize,
+    // the file system is on a disk rather than a RamDisk
+    pub disk: bool,
+    problems: [Problem; MAX_PROBLEMS],
+    len: usize,
+    // problems there wasn't room to keep
+    pub more: usize,
+}
+
+impl Report {
+    fn new(disk: bool) -> Self {
+        Report {
+            files: 0,
+            dirs: 0,
+            blocks: 0,
+            disk,
+            problems: [Problem::BadDirectory; MAX_PROBLEMS],
+            len: 0,
+            more: 0,
+        }
+    }
+
+    fn push(&mut self, problem: Problem) {
+        if self.len < MAX_PROBLEMS {
+            self.problems[self.len] = problem;
+            self.len += 1;
+        } else {
+            self.more += 1;
+        }
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems[..self.len]
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.len == 0 && self.more == 0
+    }
+}
+
+// What the directories say, as opposed to the inode bitmap. The same walk
+// does the fixing for repair, since what it'd fix depends on what it's
+// already been through.
+struct Names {
+    // the name each inode was first found under, the top directory's is all zeros
+    named: [Option<Name>; NUM_INODES],
+    fix: bool,
+}
+
+impl Names {
+    fn walk(fs: &mut FsType, report: &mut Report, fix: bool) -> Result<Self, FileSystemError> {
+        let mut names = Names {
+            named: [None; NUM_INODES],
+            fix,
+        };
+        names.named[ROOT] = Some([0; MAX_FILENAME_BYTES]);
+        names.walk_from(fs, ROOT, report)?;
+        // What's left is in no directory. A directory found here brings
+        // everything in it back with it, so only it needs a name.
+        for inode in 1..NUM_INODES {
+            if !fs.inode_used(inode)? || names.named[inode].is_some() {
+                continue;
+            }
+            report.push(Problem::Unnamed(inode));
+            let lost = lost(inode);
+            // if that's taken too, or there's no room, it stays reported
+            if fix && fs.lookup(ROOT, &lost)?.is_none() {
+                match fs.add_entry(ROOT, &lost, inode) {
+                    Ok(()) | Err(FileSystemError::DiskFull | FileSystemError::TooManyFiles) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            names.named[inode] = Some(lost);
+            names.walk_from(fs, inode, report)?;
+        }
+        Ok(names)
+    }
+
+    // Everything under the directory start, without recursing, since
+    // a damaged disk could make the directories go arbitrarily deep
+    fn walk_from(
+        &mut self,
+        fs: &mut FsType,
+        start: usize,
+        report: &mut Report,
+    ) -> Result<(), FileSystemError> {
+        // each inode is only gone into once, so it never needs more room than this
+        let mut stack = [0; NUM_INODES];
+        stack[0] = start;
+        let mut depth = 1;
+        while depth > 0 {
+            depth -= 1;
+            let dir = stack[depth];
+            let contents = fs.inode(dir)?;
+            if !contents.is_dir || contents.blocks_in_use().is_none() {
+                continue;
+            }
+            let mut seen = [[0; MAX_FILENAME_BYTES]; NUM_INODES];
+            let mut count = 0;
+            for slot in 0..contents.slots() {
+                let (name, inode) = fs.entry(&contents, slot)?;
+                if name[0] == 0 {
+                    continue;
+                }
+                let problem = if inode >= NUM_INODES || !fs.inode_used(inode)? {
+                    Some(Problem::Dangling(name))
+                } else if self.named[inode].is_some() {
+                    Some(Problem::Linked(name))
+                } else {
+                    None
+                };
+                if let Some(problem) = problem {
+                    report.push(problem);
+                    if self.fix {
+                        fs.set_entry(dir, slot, &[0; MAX_FILENAME_BYTES], 0)?;
+                    }
+                    continue;
+                }
+
+                let mut name = name;
+                if !is_text(&name) || seen[..count].contains(&name) {
+                    report.push(if is_text(&name) {
+                        Problem::Duplicate(name)
+                    } else {
+                        Problem::BadName(inode)
+                    });
+                    let lost = lost(inode);
+                    if self.fix && fs.lookup(dir, &lost)?.is_none() {
+                        fs.set_entry(dir, slot, &lost, inode)?;
+                        name = lost;
+                    }
+                }
+                self.named[inode] = Some(name);
+                if is_text(&name) && !seen[..count].contains(&name) {
+                    seen[count] = name;
+                    count += 1;
+                }
+
+                // one that can't be read still counts, there's just no going into it
+                let child = fs.inode(inode)?;
+                if child.is_dir {
+                    report.dirs += 1;
+                } else {
+                    report.files += 1;
+                }
+                if child.blocks_in_use().is_none() {
+                    report.push(Problem::Unreadable(name));
+                } else if child.is_dir {
+                    stack[depth] = inode;
+                    depth += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// What the inodes say, as opposed to the block bitmap
+struct Blocks {
+    // the first inode found with each block
+    owner: [Option<usize>; NUM_BLOCKS],
+    // inodes with a block an earlier one has, and the earlier ones
+    shared: [bool; NUM_INODES],
+}
+
+impl Blocks {
+    fn walk(fs: &FsType, names: &Names, report: &mut Report) -> Result<Self, FileSystemError> {
+        let mut walk = Blocks {
+            owner: [None; NUM_BLOCKS],
+            shared: [false; NUM_INODES],
+        };
+        // the bitmaps and the inode table
+        for owner in &mut walk.owner[..FIRST_DATA_BLOCK] {
+            *owner = Some(ROOT);
+        }
+        let name = |inode: usize| names.named[inode].unwrap_or([0; MAX_FILENAME_BYTES]);
+        for inode in 0..NUM_INODES {
+            if !fs.inode_used(inode)? {
+                continue;
+            }
+            let contents = fs.inode(inode)?;
+            let Some(blocks) = contents.blocks_in_use() else {
+                // whatever of it is on the disk isn't leaked, it might still be needed
+                for block in claimed(&contents) {
+                    walk.owner[block].get_or_insert(inode);
+                }
+                continue;
+            };
+            for block in blocks {
+                match walk.owner[*block as usize] {
+                    Some(other) => {
+                        if !walk.shared[inode] {
+                            let other = (other != ROOT).then(|| name(other));
+                            report.push(Problem::Shared(name(inode), other));
+                        }
+                        walk.shared[inode] = true;
+                        walk.shared[other] = true;
+                    }
+                    None => walk.owner[*block as usize] = Some(inode),
+                }
+            }
+        }
+        Ok(walk)
+    }
+}
+
+// Every block number inode has that's a data block, even if it's damaged
+fn claimed(inode: &Inode) -> impl Iterator<Item = usize> + '_ {
+    let count = usize::min(inode.num_blocks(), inode.blocks.len());
+    inode.blocks[..count]
+        .iter()
+        .map(|b| *b as usize)
+        .filter(|b| (FIRST_DATA_BLOCK..NUM_BLOCKS).contains(b))
+}
+
+// Only the disk itself failing is an error, anything wrong
+// with what's on it is in the report
+pub(crate) fn check(fs: &mut FsType) -> Result<Report, FileSystemError> {
+    let mut report = Report::new(fs.disk().is_present());
+    report.blocks = NUM_BLOCKS - fs.free_blocks()?;
+    let root = fs.inode(ROOT)?;
+    if !fs.inode_used(ROOT)? || !root.is_dir || root.blocks_in_use().is_none() {
+        report.push(Problem::BadDirectory);
+        return Ok(report);
+    }
+
+    let names = Names::walk(fs, &mut report, false)?;
+    let walk = Blocks::walk(fs, &names, &mut report)?;
+    let (mut leaked, mut unmarked) = (0, 0);
+    for block in 0..NUM_BLOCKS {
+        match (fs.block_used(block)?, walk.owner[block].is_some()) {
+            (true, false) if block >= FIRST_DATA_BLOCK => leaked += 1,
+            (false, true) => unmarked += 1,
+            _ => {}
+        }
+    }
+    if leaked > 0 {
+        report.push(Problem::Leaked(leaked));
+    }
+    if unmarked > 0 {
+        report.push(Problem::Unmarked(unmarked));
+    }
+    Ok(report)
+}
+
+// Fixes what can be fixed, then checks again, so what's left is what
+// couldn't be:
+//
+// - a damaged top directory is left alone, since there's nothing to go
+//   on but it. Formatting is the only way on from there.
+// - a file or directory that can't be read is left as it is, and one
+//   that shares a block gets its own copy of it, as long as there's a
+//   free block to copy into. Only one of them can have had the right
+//   contents.
+// - the bitmap is rebuilt from the inodes, which frees leaked blocks
+// - names with no file, or for one that's already got a name, are
+//   dropped. A file with no name goes in the top directory, and one
+//   whose name isn't text or is taken is renamed, as lost<inode>.
+pub(crate) fn repair(fs: &mut FsType) -> Result<Report, FileSystemError> {
+    let report = check(fs)?;
+    let bad_directory = report
+        .problems()
+        .iter()
+        .any(|p| matches!(p, Problem::BadDirectory));
+    if !report.is_clean() && !bad_directory {
+        fix_blocks(fs)?;
+        // what it finds was already in the first report
+        Names::walk(fs, &mut Report::new(false), true)?;
+        fs.disk_mut().flush()?;
+    }
+    check(fs)
+}
+
+fn fix_blocks(fs: &mut FsType) -> Result<(), FileSystemError> {
+    // every block still claimed stays in use, even by a damaged file,
+    // so nothing it might share gets handed out
+    let mut in_use = [false; NUM_BLOCKS];
+    in_use[..FIRST_DATA_BLOCK].fill(true);
+    for inode in 0..NUM_INODES {
+        if fs.inode_used(inode)? {
+            for block in claimed(&fs.inode(inode)?) {
+                in_use[block] = true;
+            }
+        }
+    }
+    for (block, used) in in_use.iter().enumerate() {
+        fs.set_block_used(block, *used)?;
+    }
+
+    // what's still shared gets split up
+    let mut owned = [false; NUM_BLOCKS];
+    for inode in 0..NUM_INODES {
+        if !fs.inode_used(inode)? {
+            continue;
+        }
+        let mut contents = fs.inode(inode)?;
+        let count = match contents.blocks_in_use() {
+            Some(blocks) => blocks.len(),
+            None => continue,
+        };
+        for block in &mut contents.blocks[..count] {
+            if !owned[*block as usize] {
+                owned[*block as usize] = true;
+                continue;
+            }
+            let Some(copy) = (FIRST_DATA_BLOCK..NUM_BLOCKS).find(|b| !in_use[*b]) else {
+                break;
+            };
+            in_use[copy] = true;
+            fs.set_block_used(copy, true)?;
+            fs.copy_block(*block as usize, copy)?;
+            owned[copy] = true;
+            *block = copy as u8;
+        }
+        fs.set_inode(inode, &contents)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Disk;
+
+    fn write(fs: &mut FsType, filename: &str, data: &[u8]) {
+        let fd = fs.open_create(filename).unwrap();
+        fs.write(fd, data).unwrap();
+        fs.close(fd).unwrap();
+    }
+
+    fn seeded() -> FsType {
+        let mut fs = FsType::new(Disk::ram()).unwrap();
+        write(&mut fs, "a", &[1; 300]);
+        write(&mut fs, "b", b"bee");
+        fs
+    }
+
+    fn get(fs: &mut FsType, name: &str) -> ([u8; 300], usize) {
+        let mut buffer = [0; 300];
+        let fd = fs.open_read(name).unwrap();
+        let n = fs.read(fd, &mut buffer).unwrap();
+        fs.close(fd).unwrap();
+        (buffer, n)
+    }
+
+    #[test]
+    fn clean_file_system() {
+        let mut fs = seeded();
+        let report = check(&mut fs).unwrap();
+        assert!(report.is_clean());
+        assert!(!report.disk);
+        assert_eq!(report.files, 2);
+        assert_eq!(report.blocks, NUM_BLOCKS - fs.free_blocks().unwrap());
+    }
+
+    #[test]
+    fn shared_blocks_are_split_and_the_leak_freed() {
+        let mut fs = seeded();
+        let free = fs.free_blocks().unwrap();
+        // b is pointed at a's first block, leaking its own
+        let a = fs.inode(1).unwrap();
+        let mut b = fs.inode(2).unwrap();
+        b.blocks[0] = a.blocks[0];
+        fs.set_inode(2, &b).unwrap();
+
+        let report = check(&mut fs).unwrap();
+        assert!(matches!(report.problems(), [Problem::Shared(..), Problem::Leaked(1)]));
+
+        // b gets a copy of what it points at
+        let report = repair(&mut fs).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(fs.free_blocks().unwrap(), free);
+        write(&mut fs, "a", &[2; 300]);
+        let (b, n) = get(&mut fs, "b");
+        assert_eq!(&b[..n], [1, 1, 1]);
+    }
+
+    #[test]
+    fn blocks_marked_free_are_marked_again() {
+        let mut fs = seeded();
+        let a = fs.inode(1).unwrap();
+        fs.set_block_used(a.blocks[1] as usize, false).unwrap();
+        let report = check(&mut fs).unwrap();
+        assert!(matches!(report.problems(), [Problem::Unmarked(1)]));
+
+        assert!(repair(&mut fs).unwrap().is_clean());
+        assert!(fs.block_used(a.blocks[1] as usize).unwrap());
+    }
+
+    #[test]
+    fn dangling_and_unnamed_entries() {
+        let mut fs = seeded();
+        let free = fs.free_blocks().unwrap();
+        // a's inode is let go without its name, b loses its name
+        fs.set_inode_used(1, false).unwrap();
+        fs.set_entry(ROOT, 1, &[0; MAX_FILENAME_BYTES], 0).unwrap();
+        let report = check(&mut fs).unwrap();
+        assert!(matches!(
+            report.problems(),
+            [Problem::Dangling(_), Problem::Unnamed(2), Problem::Leaked(2)]
+        ));
+
+        assert!(repair(&mut fs).unwrap().is_clean());
+        assert!(!fs.exists("a"));
+        let (b, n) = get(&mut fs, "lost2");
+        assert_eq!(&b[..n], b"bee");
+        // a's two blocks come back with it
+        assert_eq!(fs.free_blocks().unwrap(), free + 2);
+    }
+
+    #[test]
+    fn damage_that_cant_be_fixed_stays() {
+        let mut fs = seeded();
+        let mut a = fs.inode(1).unwrap();
+        a.blocks[0] = 0;
+        fs.set_inode(1, &a).unwrap();
+        let report = repair(&mut fs).unwrap();
+        assert!(matches!(report.problems(), [Problem::Unreadable(_)]));
+
+        fs.set_inode_used(ROOT, false).unwrap();
+        let report = repair(&mut fs).unwrap();
+        assert!(matches!(report.problems(), [Problem::BadDirectory]));
+    }
+
+    #[test]
+    fn a_lost_directory_comes_back_with_everything_in_it() {
+        let mut fs = seeded();
+        fs.mkdir("lib").unwrap();
+        fs.mkdir("lib/deep").unwrap();
+        write(&mut fs, "lib/deep/c", b"sea");
+        // lib is inode 3, in the third slot, and b's put in it as well
+        fs.set_entry(ROOT, 2, &[0; MAX_FILENAME_BYTES], 0).unwrap();
+        fs.add_entry(4, &to_name(b"b"), 2).unwrap();
+        let report = check(&mut fs).unwrap();
+        assert!(matches!(
+            report.problems(),
+            [Problem::Unnamed(3), Problem::Linked(_)]
+        ));
+
+        let report = repair(&mut fs).unwrap();
+        assert!(report.is_clean());
+        assert_eq!((report.files, report.dirs), (3, 2));
+        let (c, n) = get(&mut fs, "lost3/deep/c");
+        assert_eq!(&c[..n], b"sea");
+        let (count, _) = fs.list_directory("lost3/deep").unwrap();
+        assert_eq!(count, 1);
+    }
+}
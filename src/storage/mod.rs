@@ -0,0 +1,334 @@
+// Keeping files between boots. FsType is mounted straight on the disk,
+// each of its blocks at the start of the sector with the same number, so
+// a file is on the disk as soon as it's closed. Without a disk it runs on
+// a RamDisk instead, and files are gone after a reboot.
+//
+// A disk image is just those sectors, with any blank ones at the end left
+// off. That's how the sample files get in: seed.img is copied onto a new
+// disk, or into the RamDisk, and src/bin/swimimg.rs builds and reads them.
+
+mod ata;
+pub mod fsck;
+
+use core::fmt;
+
+use ramdisk::RamDisk;
+
+use crate::{
+    fs::{self, FileSystemError},
+    BLOCK_SIZE, NUM_BLOCKS, SEED_IMAGE,
+};
+#[cfg(feature = "std")]
+use crate::{scratch::with_file_buffer, FsType};
+pub use ata::Ata;
+
+pub const SECTOR_SIZE: usize = 512;
+
+const _: () = assert!(BLOCK_SIZE <= SECTOR_SIZE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskError {
+    NoDisk,
+    OutOfRange,
+    Timeout,
+    Device,
+    // fewer sectors than the file system has blocks
+    TooSmall,
+}
+
+impl fmt::Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::NoDisk => write!(f, "no disk"),
+            DiskError::OutOfRange => write!(f, "past the end of the disk"),
+            DiskError::Timeout => write!(f, "disk not responding"),
+            DiskError::Device => write!(f, "disk error"),
+            DiskError::TooSmall => write!(f, "disk too small"),
+        }
+    }
+}
+
+// Anything that hands out fixed size blocks by number. Drives have
+// SECTOR_SIZE ones, the file system wants BLOCK_SIZE ones.
+pub trait BlockDevice<const SIZE: usize = SECTOR_SIZE> {
+    fn num_blocks(&self) -> usize;
+    fn read_block(&self, block: usize, buffer: &mut [u8; SIZE]) -> Result<(), DiskError>;
+    fn write_block(&mut self, block: usize, buffer: &[u8; SIZE]) -> Result<(), DiskError>;
+
+    // Makes sure every write so far has really landed
+    fn flush(&mut self) -> Result<(), DiskError> {
+        Ok(())
+    }
+}
+
+impl<const SIZE: usize, const N: usize> BlockDevice<SIZE> for RamDisk<SIZE, N> {
+    fn num_blocks(&self) -> usize {
+        N
+    }
+
+    fn read_block(&self, block: usize, buffer: &mut [u8; SIZE]) -> Result<(), DiskError> {
+        if block >= N {
+            return Err(DiskError::OutOfRange);
+        }
+        self.read(block, buffer);
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: usize, buffer: &[u8; SIZE]) -> Result<(), DiskError> {
+        if block >= N {
+            return Err(DiskError::OutOfRange);
+        }
+        self.write(block, buffer);
+        Ok(())
+    }
+}
+
+// What FsType is mounted on. It lives in the file system, which is
+// built in place, so the RamDisk being big doesn't cost anything.
+#[allow(clippy::large_enum_variant)]
+pub enum Disk {
+    // files are gone after a reboot
+    Ram(RamDisk<BLOCK_SIZE, NUM_BLOCKS>),
+    Ata(Ata),
+    #[cfg(feature = "std")]
+    Memory(MemDisk),
+}
+
+impl Disk {
+    pub fn ram() -> Self {
+        Disk::Ram(RamDisk::new())
+    }
+
+    // The first ATA drive that isn't the one we booted
+    // from, or just a RamDisk when there isn't one
+    pub fn probe() -> Self {
+        // the harness has no ports to poke at
+        #[cfg(not(feature = "std"))]
+        if let Some(ata) = Ata::probe() {
+            return Disk::Ata(ata);
+        }
+        Disk::ram()
+    }
+
+    pub fn is_present(&self) -> bool {
+        !matches!(self, Disk::Ram(_))
+    }
+}
+
+impl BlockDevice<BLOCK_SIZE> for Disk {
+    fn num_blocks(&self) -> usize {
+        match self {
+            Disk::Ram(ram) => BlockDevice::num_blocks(ram),
+            Disk::Ata(ata) => ata.num_blocks(),
+            #[cfg(feature = "std")]
+            Disk::Memory(mem) => mem.num_blocks(),
+        }
+    }
+
+    fn read_block(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+        match self {
+            Disk::Ram(ram) => ram.read_block(block, buffer),
+            Disk::Ata(ata) => read_in_sector(ata, block, buffer),
+            #[cfg(feature = "std")]
+            Disk::Memory(mem) => read_in_sector(mem, block, buffer),
+        }
+    }
+
+    fn write_block(&mut self, block: usize, buffer: &[u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+        match self {
+            Disk::Ram(ram) => ram.write_block(block, buffer),
+            Disk::Ata(ata) => write_in_sector(ata, block, buffer),
+            #[cfg(feature = "std")]
+            Disk::Memory(mem) => write_in_sector(mem, block, buffer),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), DiskError> {
+        match self {
+            Disk::Ata(ata) => ata.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// A block takes up the start of a sector and the rest is left as zeros,
+// so writing one never has to read the sector first
+fn read_in_sector(
+    disk: &impl BlockDevice,
+    block: usize,
+    buffer: &mut [u8; BLOCK_SIZE],
+) -> Result<(), DiskError> {
+    let mut sector = [0; SECTOR_SIZE];
+    disk.read_block(block, &mut sector)?;
+    buffer.copy_from_slice(&sector[..BLOCK_SIZE]);
+    Ok(())
+}
+
+fn write_in_sector(
+    disk: &mut impl BlockDevice,
+    block: usize,
+    buffer: &[u8; BLOCK_SIZE],
+) -> Result<(), DiskError> {
+    let mut sector = [0; SECTOR_SIZE];
+    sector[..BLOCK_SIZE].copy_from_slice(buffer);
+    disk.write_block(block, &sector)
+}
+
+// An image that's baked into the kernel, like seed.img. It can't be
+// written to, so it's only ever something to copy onto a disk.
+pub struct RomDisk(pub &'static [u8]);
+
+impl BlockDevice<BLOCK_SIZE> for RomDisk {
+    // the blank sectors left off the end are still there, as far as anyone can tell
+    fn num_blocks(&self) -> usize {
+        usize::max(NUM_BLOCKS, self.0.len().div_ceil(SECTOR_SIZE))
+    }
+
+    fn read_block(&self, block: usize, buffer: &mut [u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+        if block >= self.num_blocks() {
+            return Err(DiskError::OutOfRange);
+        }
+        let start = usize::min(block * SECTOR_SIZE, self.0.len());
+        let bytes = &self.0[start..usize::min(start + BLOCK_SIZE, self.0.len())];
+        buffer.fill(0);
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_block(&mut self, _block: usize, _buffer: &[u8; BLOCK_SIZE]) -> Result<(), DiskError> {
+        Err(DiskError::Device)
+    }
+}
+
+// A disk that's just memory, for the harness and for building
+// images on the host. Its bytes are exactly what a -drive file holds.
+#[cfg(feature = "std")]
+pub struct MemDisk {
+    blocks: Vec<[u8; SECTOR_SIZE]>,
+}
+
+#[cfg(feature = "std")]
+impl MemDisk {
+    pub fn new(num_blocks: usize) -> Self {
+        Self {
+            blocks: vec![[0; SECTOR_SIZE]; num_blocks],
+        }
+    }
+
+    // Pads out the last sector with zeros, and puts back any
+    // blank ones an image had left off the end
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut disk = Self::new(usize::max(NUM_BLOCKS, bytes.len().div_ceil(SECTOR_SIZE)));
+        for (block, chunk) in disk.blocks.iter_mut().zip(bytes.chunks(SECTOR_SIZE)) {
+            block[..chunk.len()].copy_from_slice(chunk);
+        }
+        disk
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.blocks.concat()
+    }
+
+    // Leaves off the blank sectors at the end
+    pub fn trim(&mut self) {
+        let used = self.blocks.iter().rposition(|b| b.iter().any(|x| *x != 0));
+        self.blocks.truncate(used.map_or(0, |last| last + 1));
+    }
+}
+
+#[cfg(feature = "std")]
+impl BlockDevice for MemDisk {
+    fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn read_block(&self, block: usize, buffer: &mut [u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        *buffer = *self.blocks.get(block).ok_or(DiskError::OutOfRange)?;
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: usize, buffer: &[u8; SECTOR_SIZE]) -> Result<(), DiskError> {
+        *self.blocks.get_mut(block).ok_or(DiskError::OutOfRange)? = *buffer;
+        Ok(())
+    }
+}
+
+// Copies the sample files onto disk, which is left formatted with
+// them on it. A seed.img that isn't an image leaves disk alone.
+pub(crate) fn seed(disk: &mut Disk) -> Result<(), FileSystemError> {
+    let seed = RomDisk(SEED_IMAGE);
+    if !fs::is_formatted(&seed)? {
+        return Err(FileSystemError::NotFormatted);
+    }
+    let mut buffer = [0; BLOCK_SIZE];
+    for block in 0..NUM_BLOCKS {
+        seed.read_block(block, &mut buffer)?;
+        disk.write_block(block, &buffer)?;
+    }
+    Ok(disk.flush()?)
+}
+
+// Builds an image from (path, contents) pairs, making the directories
+// they're in along the way. They go through a real file system, so
+// anything the OS couldn't hold is refused here rather than at boot.
+#[cfg(feature = "std")]
+pub fn pack<'a>(
+    files: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+) -> Result<MemDisk, FileSystemError> {
+    let mut fs = FsType::new(Disk::Memory(MemDisk::new(NUM_BLOCKS)))?;
+    for (path, data) in files {
+        for (slash, _) in path.match_indices('/') {
+            match fs.mkdir(&path[..slash]) {
+                Ok(()) | Err(FileSystemError::AlreadyExists) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let fd = fs.open_create(path)?;
+        let written = fs.write(fd, data);
+        fs.close(fd)?;
+        written?;
+    }
+    let Disk::Memory(mut disk) = fs.into_disk() else {
+        unreachable!()
+    };
+    disk.trim();
+    Ok(disk)
+}
+
+// Every file on an image by its path, in the order they show up in the
+// Explorer, with what's in a directory straight after it
+#[cfg(feature = "std")]
+pub fn unpack(disk: MemDisk) -> Result<Vec<(String, Vec<u8>)>, FileSystemError> {
+    let disk = Disk::Memory(disk);
+    if !fs::is_formatted(&disk)? {
+        return Err(FileSystemError::NotFormatted);
+    }
+    let mut fs = FsType::mount(disk);
+    let mut files = Vec::new();
+    unpack_dir(&mut fs, "", &mut files)?;
+    Ok(files)
+}
+
+#[cfg(feature = "std")]
+fn unpack_dir(
+    fs: &mut FsType,
+    dir: &str,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), FileSystemError> {
+    let (count, entries) = fs.list_directory(dir)?;
+    for entry in &entries[..count] {
+        let path = format!("{dir}{}", fs::filename(&entry.name)?);
+        if entry.is_dir {
+            unpack_dir(fs, &format!("{path}/"), files)?;
+            continue;
+        }
+        let data = with_file_buffer(|buffer| {
+            let fd = fs.open_read(&path)?;
+            let n = fs.read(fd, buffer);
+            fs.close(fd)?;
+            Ok::<_, FileSystemError>(buffer[..n?].to_vec())
+        })?;
+        files.push((path, data));
+    }
+    Ok(())
+}
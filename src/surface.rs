@@ -0,0 +1,98 @@
+use core::fmt;
+
+use crate::vga::{plot, Color, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH};
+
+// Everything that draws goes through a Surface instead of calling
+// vga_buffer::plot directly. On bare metal this is the VGA text buffer,
+// but a GridSurface lets us draw into plain memory and look at the
+// result afterwards, which is the only way to test rendering off the VM.
+pub trait Surface {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode);
+}
+
+// Zero sized, since the VGA buffer itself lives at a fixed address.
+#[derive(Default)]
+pub struct VgaSurface;
+
+impl Surface for VgaSurface {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode) {
+        plot(c, col, row, color);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub color: ColorCode,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            color: ColorCode::new(Color::LightGray, Color::Black),
+        }
+    }
+}
+
+pub struct GridSurface {
+    cells: [[Cell; BUFFER_WIDTH]; BUFFER_HEIGHT],
+}
+
+impl Default for GridSurface {
+    fn default() -> Self {
+        Self {
+            cells: [[Cell::default(); BUFFER_WIDTH]; BUFFER_HEIGHT],
+        }
+    }
+}
+
+impl GridSurface {
+    pub fn cell(&self, col: usize, row: usize) -> Cell {
+        self.cells[row][col]
+    }
+
+    pub fn char_at(&self, col: usize, row: usize) -> char {
+        self.cells[row][col].c
+    }
+
+    pub fn color_at(&self, col: usize, row: usize) -> ColorCode {
+        self.cells[row][col].color
+    }
+
+    pub fn row(&self, row: usize) -> impl Iterator<Item = char> + '_ {
+        self.cells[row].iter().map(|cell| cell.c)
+    }
+
+    // Text of cols x1..=x2 on the given row, useful for looking
+    // at just the inside of one window.
+    pub fn span(&self, x1: usize, x2: usize, row: usize) -> impl Iterator<Item = char> + '_ {
+        self.cells[row][x1..=x2].iter().map(|cell| cell.c)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells = [[Cell::default(); BUFFER_WIDTH]; BUFFER_HEIGHT];
+    }
+}
+
+impl Surface for GridSurface {
+    fn plot(&mut self, c: char, col: usize, row: usize, color: ColorCode) {
+        self.cells[row][col] = Cell { c, color };
+    }
+}
+
+// Dumps the grid as 25 lines of 80 characters, so whole screens
+// can be compared against golden text.
+impl fmt::Display for GridSurface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..BUFFER_HEIGHT {
+            for c in self.row(row) {
+                fmt::Write::write_char(f, c)?;
+            }
+            if row + 1 < BUFFER_HEIGHT {
+                fmt::Write::write_char(f, '\n')?;
+            }
+        }
+        Ok(())
+    }
+}
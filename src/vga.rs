@@ -0,0 +1,57 @@
+// The parts of pluggable_interrupt_os::vga_buffer everything else draws with.
+// That crate also brings the kernel's #[panic_handler], which would clash
+// with std's, so Cargo.toml only pulls it in for the bare metal target and
+// builds for the host (the harness and swimimg) get the copies below.
+
+#[cfg(target_os = "none")]
+pub use pluggable_interrupt_os::vga_buffer::{
+    is_drawable, plot, Color, ColorCode, BUFFER_HEIGHT, BUFFER_WIDTH,
+};
+
+#[cfg(not(target_os = "none"))]
+pub use host::*;
+
+#[cfg(not(target_os = "none"))]
+mod host {
+    pub const BUFFER_WIDTH: usize = 80;
+    pub const BUFFER_HEIGHT: usize = 25;
+
+    #[allow(dead_code)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(u8)]
+    pub enum Color {
+        Black = 0,
+        Blue = 1,
+        Green = 2,
+        Cyan = 3,
+        Red = 4,
+        Magenta = 5,
+        Brown = 6,
+        LightGray = 7,
+        DarkGray = 8,
+        LightBlue = 9,
+        LightGreen = 10,
+        LightCyan = 11,
+        LightRed = 12,
+        Pink = 13,
+        Yellow = 14,
+        White = 15,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct ColorCode(u8);
+
+    impl ColorCode {
+        pub const fn new(foreground: Color, background: Color) -> ColorCode {
+            ColorCode((background as u8) << 4 | (foreground as u8))
+        }
+    }
+
+    // There's no VGA buffer off bare metal, only GridSurfaces get looked at
+    pub fn plot(_c: char, _col: usize, _row: usize, _color: ColorCode) {}
+
+    pub fn is_drawable(c: char) -> bool {
+        matches!(c, ' '..='~')
+    }
+}